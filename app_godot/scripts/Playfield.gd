@@ -1,24 +1,13 @@
-extends TileMap
+extends ForBlockUnits
 
 #enum {wait, move}
 #var state
 #@export var empty_spaces: PackedVector2Array
 
-var destroy_timer = Timer.new()
-var collapse_timer = Timer.new()
-var refill_timer = Timer.new()
-
-var grid_by_key = []
-
-func _ready():
-	var tileset_scene = AutoloadGlobalsTileset.AutoloadPlayfieldCellTileset
-	#state = move
-	setup_timers()
-	#randomize()
-	grid_by_key = make_2d_array()
-
-	#var layered_maps: Array[Array] = AutoloadGlobalsTileset.evaluate_tilemap(self, "Playfield.gd._ready()")
-	AutoloadGlobalsTileset.call_deferred("evaluate_tilemap", self, "Playfield.gd._ready()")
+# NOTE: Countdown and flow speed are driven by the game clock in ForBlockUnits (Rust), see
+# clock_pause()/clock_resume()/clock_fast_forward() and the flow_started/level_finished signals
+# NOTE: Do NOT add _ready() (or _process()) here, overriding it in the script replaces the one
+# of ForBlockUnits (Rust), which is what builds the grid (or loads level_path) and runs the clock
 
 #func make_random_cell():
 #	var tileset_scene = AutoloadGlobalsTileset.AutoloadPlayfieldCellTileset
@@ -61,56 +50,30 @@ func _on_input_event(viewport, event, shape_idx):
 		print("2")
 		pass
 		
+# Clicks go through ForBlockUnits (Rust) rather than straight to the TileMap, so that the cell
+# rules are enforced (start/goal, locked, obstacle, replacement penalties, etc); a click on a
+# cell that does not allow it ends up in _on_placement_rejected() instead
 func _unhandled_input(event):
-	var k_layer = 0
-	var next_cells_queue = []
 	if event is InputEventMouseButton and event.pressed :
-		# force this cell to become void?
-		var tile_data = get_clicked_tile(event.position, k_layer)
-
-		# NOTE: if clicked cell atlas is (-1, -1), user clicked OUTSIDE the grid
-		if tile_data.GridMapCoordinate.x < 0 or tile_data.GridMapCoordinate.y < 0:
-			# opt out
-			pass
-		else:
-			if event.button_index == MOUSE_BUTTON_LEFT:
-				# pop the queue and replace it
-				print("3a: Key=" + str(tile_data.Key) + ", Layer=" + str(tile_data.Layer) + ", GridMapCoordinate=" + str(tile_data.GridMapCoordinate))
-				#var next_cell = next_cells_queue.pop_front()
-				#next_cells_queue.append(make_random_cell())
-				#grid[clicked_cell_vec2.x][clicked_cell_vec2.y] = next_cell["atlas"]
-				#set_cell(0, clicked_cell_vec2, 0, next_cell["atlas"])
-				var next_cell = get_parent().get_node("TileMap_NextTiles").get_head()
-				#self.set_cell(tile_data.Layer, tile_data.GridMapCoordinate, AutoloadGlobalsTileset.possible_block_units_kvp[next_cell]["source_id"], Vector2i(0, 0), 0)
-				AutoloadGlobalsTileset.set_cell_by_key(self, tile_data.Layer, tile_data.GridMapCoordinate, next_cell)
-
-			elif event.button_index == MOUSE_BUTTON_RIGHT:
-				# erase it using void_cell_atlas
-				print("3b: Key=" + str(tile_data.Key) + ", Layer=" + str(tile_data.Layer) + ", GridMapCoordinate=" + str(tile_data.GridMapCoordinate))
-				#grid[clicked_cell_vec2.x][clicked_cell_vec2.y] = void_cell_atlas
-				#set_cell(0, clicked_cell_vec2, 0, void_cell_atlas)
-				
-				# first, grab the head of the queue
-				#AutoloadGlobalsTileset.cell_clicked.emit()
-				var next_cell = get_parent().get_node("TileMap_NextTiles").get_head()
-				#self.set_cell(tile_data.Layer, tile_data.GridMapCoordinate, AutoloadGlobalsTileset.possible_block_units_kvp[next_cell]["source_id"], Vector2i(0, 0), 0)
-				AutoloadGlobalsTileset.set_cell(self, tile_data.Layer, tile_data.GridMapCoordinate, next_cell)
-
-func setup_timers():
-	destroy_timer.connect("timeout", Callable(self, "destroy_matches"))
-	destroy_timer.set_one_shot(true)
-	destroy_timer.set_wait_time(0.2)
-	add_child(destroy_timer)
-
-	collapse_timer.connect("timeout", Callable(self, "collapse_columns"))
-	collapse_timer.set_one_shot(true)
-	collapse_timer.set_wait_time(0.2)
-	add_child(collapse_timer)
+		var cell_position = self.local_to_map(self.get_local_mouse_position())
+		if event.button_index == MOUSE_BUTTON_LEFT:
+			# place the head of the queue, facing the way the queue handed it out (as-is if
+			# queue_map_path is not set to a QueueTileMap, place_from_queue() reports that one)
+			var orientation = 0
+			if has_node(queue_map_path) and get_node(queue_map_path) is ForBlockUnits:
+				var head = get_node(queue_map_path).queue_peek(1)
+				if head.size() > 0:
+					orientation = head[0]["orientation"]
+			place_from_queue(cell_position, orientation)
+		elif event.button_index == MOUSE_BUTTON_RIGHT:
+			# erase it (back to VOID)
+			clear_cell(cell_position)
 
-	refill_timer.connect("timeout", Callable(self, "refill_columns"))
-	refill_timer.set_one_shot(true)
-	refill_timer.set_wait_time(0.2)
-	add_child(refill_timer)
+# connected (in bg_default.tscn) to our own placement_rejected signal; 'reason' is one of
+# "out_of_grid", "start_cell", "goal_cell", "locked", "obstacle", "not_replaceable",
+# "flow_in_cell", "cooldown"
+func _on_placement_rejected(cell_position: Vector2i, reason: String):
+	print("Playfield.gd - cannot place at " + str(cell_position) + ": " + reason)
 
 # based on dimension (width, height) defined in TileMap via visual edtior Inspector, create a 2D array
 # of the same dimension and fill it with dictionary key-names as a lookup
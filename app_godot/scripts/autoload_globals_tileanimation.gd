@@ -7,6 +7,8 @@ extends Node
 # "empty" - when it is initially dropped onto place (see _ready)
 # "filled" - when resource is full/filled on the block
 # TODO: Want a set of animations for full resource versus partial resrouce to represent amount and (pressure, flow, motor, etc) strength of the block
+# NOTE: The amounts are there already: ForBlockUnits get_cell_fill() (0.0 to 1.0), get_cell_pressure(),
+# get_cell_volume() and is_cell_flowing(), updated on every flow tick; only the animations are missing
 enum AnimationState { 
     NONE,
     ANIMATE, 
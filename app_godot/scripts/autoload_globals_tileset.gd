@@ -11,23 +11,28 @@ extends Node
 
 
 # cell_clicked signal notifies subscribers to pop the head and push to tail, animate, etc
+# NOTE: For Playfields driven by ForBlockUnits (Rust), prefer its cell_placed/cell_replaced signals
+# which are emitted after the placement has actually been accepted (i.e. not on a locked cell)
 signal cell_clicked(cell_value: AutoloadGlobalsTileset.BLOCK_KEYS, cell_position: Vector2i)
 
 # constants
+# NOTE: Order MUST match the Rust BlockKeys enum (internal_primitives) since values are passed
+# back and forth as int (i.e. ForBlockUnits.get_cell_key(), or "key" of queue_get_head())
 enum BLOCK_KEYS {
-	UNDEFINED,
-	VOID,
-	LINE_BLOCK1,
-	LINE_BLOCK2,
-	LINE_BLOCK3,
-	LINE_BLOCK4,
-	ROUTE1_STRAIGHT,
-	ROUTE1_90DEG,
-	ROUTE2,
-	ROUTE3,
-	ROUTE_JOIN2T,
-	ROUTE_JOIN3,
-	JUNCTION,
+	UNDEFINED,		# BlockKeys::Undefined
+	VOID,			# BlockKeys::Void
+	LINE_BLOCK1,	# BlockKeys::LineBlock1Edge
+	LINE_BLOCK2,	# BlockKeys::LineBlock2Corner
+	LINE_BLOCK3,	# BlockKeys::LineBlock3T
+	LINE_BLOCK4,	# BlockKeys::LineBlock4All
+	JUNCTION,		# BlockKeys::Router1Cross
+	ROUTE1_STRAIGHT,	# BlockKeys::Router1Straight
+	ROUTE1_90DEG,	# BlockKeys::Router1Corner
+	ROUTE2,			# BlockKeys::Router1Tee
+	ROUTE3,			# BlockKeys::Router
+	ROUTE_JOIN2T,	# BlockKeys::RouteJoin2To1
+	ROUTE_JOIN3,	# BlockKeys::RouteJoin3To1
+	TOWER,			# BlockKeys::Tower (second game mode, no scene in the playfield TileSet yet)
 }
 
 # When the atlas position changes in TileSet "playfield_cell_tileset.tres", in which TileSet has been
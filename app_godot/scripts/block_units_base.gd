@@ -2,10 +2,12 @@ class_name BlockUnitBase extends Node2D
 
 var state: AutoloadGlobalsTileAnimation.AnimationState
 var total_frames: int
+var entry_side: Vector2i	# side the stream came in through, Vector2i(0, 0) if it never did
 
 func _init():
 	state = AutoloadGlobalsTileAnimation.AnimationState.EMPTY
 	total_frames = 0
+	entry_side = Vector2i(0, 0)
 
 # Called when the node enters the scene tree for the first time.
 func _ready() -> void:
@@ -17,17 +19,9 @@ func _ready() -> void:
 
 # Called every frame. 'delta' is the elapsed time since the previous frame.
 func _process(delta: float) -> void:
-	var sprite = get_node("AnimatedSprite2D")
-	if sprite == null:
-		return
-
-	if state != AutoloadGlobalsTileAnimation.AnimationState.ANIMATE:
-		# if not playing, animate it
-		cb_animate()
-
-	# TODO: Signal on entry
-	
-	# TODO: Signal on exit
+	# NOTE: Nothing to do per frame; what to play is pushed by ForBlockUnits (see cb_flow_state)
+	# whenever the stream gets to, or moves on from, this tile
+	pass
 
 
 # signal received to animate
@@ -44,3 +38,22 @@ func cb_animate() -> void:
 	# trigger it
 	sprite.play(str_state)
 
+# signal received (from ForBlockUnits, on each flow tick the state of this tile changed):
+# 'new_state' is AutoloadGlobalsTileAnimation.AnimationState, 'side' the side the stream came in
+# through, and 'reversed' is true if that is not the side the "animate" frames start from
+func cb_flow_state(new_state: int, side: Vector2i, reversed: bool) -> void:
+	var sprite = get_node("AnimatedSprite2D")
+	if sprite == null:
+		return
+
+	state = new_state as AutoloadGlobalsTileAnimation.AnimationState
+	entry_side = side
+	if state == AutoloadGlobalsTileAnimation.AnimationState.NONE:
+		sprite.stop()
+		return
+	var str_state = AutoloadGlobalsTileAnimation.kvp_animation_state[state] as String
+	total_frames = sprite.sprite_frames.get_frame_count(str_state)
+	if reversed and state == AutoloadGlobalsTileAnimation.AnimationState.ANIMATE:
+		sprite.play_backwards(str_state)
+	else:
+		sprite.play(str_state)
@@ -6,12 +6,30 @@ use godot::prelude::*;
 unsafe impl ExtensionLibrary for entry_point::ForBlockUnits {}
 
 pub mod entry_point {
-    use std::{cell::Ref, collections::HashMap};
+    use std::collections::HashMap;
 
     use godot::{
         engine::{ITileMap, TileMap, TileSetScenesCollectionSource, TileSetSource},
+        engine::tile_set::{TileLayout, TileOffsetAxis, TileShape},
         prelude::*,
     };
+    use godot::engine::{file_access::ModeFlags, FileAccess};
+    use internal_primitives::{
+        animation::{cell_animation, CellAnimation},
+        connectivity::Direction,
+        flow::{CellFlow, FlowEvent, FlowHead},
+        godot_convert::{BlockUnitCellDictionaryType, BlockUnitCellKVPValue},
+        hint::{rank_cells_for_heads, HintSettings, MAX_LOOKAHEAD},
+        level::Level,
+        orientation::{OrientedBlock, Orientation},
+        placement::{PlacementError, PlacementOutcome},
+        replay::ReplayRecorder,
+        session::{GameSession, SessionEvent, SessionInput},
+        tile_queue::{TileQueue, TileQueueWeights},
+        topology::GridTopology,
+        cell_at, BlockKeys, BlockUnitCell, BlockUnitsMapType, CellFlags, CellIdType, CellMap,
+        GridPosition, LayerType,
+    };
     // TileMap::get_tileset() returns Option<Gd<crate::engine::TileSet>>, meaning you can
     // only have at most 1 TileSet (or None) per TileMap.  And at the same time, we will
     // assume that TileSet will be attached to the TileMap via the Godot Editor, so that
@@ -24,95 +42,46 @@ pub mod entry_point {
     // * PlayfieldTileMap: This is the main tilemap that the player will interact with
     // * QueueTileMap: This is the tilemap that will show the next 3 tiles that will be
     //   available to the player
-    #[derive(Debug, Clone, Copy, PartialEq)]
-    pub enum BlockUnitsMapType {
-        Undefined,
-        PlayfieldTileMap, // an NxM grid
-        QueueTileMap, // an 1xN or Nx1 grid, either way, assumes that map can be flattenend into a 1D array
-    }
-
-    // NOTE: This enum will need to be assigned to the TileSetScenesCollectionSource scenes (.tscn)
-    // as a meta-data so that we can identify not by PackedScene, but by the enum value
-    #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)] // need both Hash and Eq because it's used as dictionary key
-    pub enum BlockKeys {
-        Undefined,
-        Void,             // basically, empty space
-        LineBlock1Edge,   // blocks one side
-        LineBlock2Corner, // blocks two sides (90 degrees)
-        LineBlock3T,      // blocks three sides
-        LineBlock4All,    // blocks all sides
-        Router1Cross,     // Cross shape (top-down 1 in 1 out, and left-right 1 in 1 out)
-        Router1Straight,  // straight line (1 in 1 out)
-        Router1Corner,    // 90 degree turn, 1 in 1 out
-        Router1Tee,       // T shape (1 in, 2 out)
-        Router,           // 1 in, 3 out
-        RouteJoin2To1,    // 2 in, 1 out
-        RouteJoin3To1,    // 3 in, 1 out
-    }
-    type CellIdType = i32; // this is the id of the cell (i.e. the type of block)
-    type LayerType = i32;
-    struct BlockUnitCell {
-        key: BlockKeys,
-        position: Vector2i, // we're using godot primitives
-        layer: LayerType,   // this is the layer of the cell
-        cell_source_id: CellIdType,
-    }
-    // as much as I appreciate Tuples, they are anonymous and are ref'ed by positon (i.e. tup.0, tup.1, and tup.2, etc)
-    // so I'll stick with struct for my KVP values in case it grows fatter than 2 elements...
-    struct BlockUnitCellKVPValue {
-        source_id: CellIdType,
-        scene: Option<Gd<PackedScene>>,
-        resource_path: Option<GString>, // use GString here?
-    }
-    // e.g. let mut my_dict: BlockUnitCellDictionaryType<'static> = HashMap::new();  // Key: BlockKeys, Value: BlockUnitCellKVPValue
-    type BlockUnitCellDictionaryType = HashMap<BlockKeys, BlockUnitCellKVPValue>;
+    // NOTE: BlockUnitsMapType, BlockKeys, BlockUnitCell (and the conversions from/to Godot types)
+    // all come from internal_primitives, so that the Rust-side logic (i.e. TileQueue, FlowSimulator)
+    // and this extension agree on the same values
 
-    // To be able to render it on the Godot editor (i.e. Inspector), we need to have a way to convert
-    // the enum to a Godot primitive type (i.e. GString)
-    // Once From<T> is implemented (conversion traits), you can then do "into()"
-    // i.e. let gstring: GString = TileMapType::Undefined.into();
-    impl From<BlockUnitsMapType> for GString {
-        fn from(tile_map_type: BlockUnitsMapType) -> Self {
-            match tile_map_type {
-                BlockUnitsMapType::Undefined => "Undefined".into(),
-                BlockUnitsMapType::PlayfieldTileMap => "PlayfieldTileMap".into(),
-                BlockUnitsMapType::QueueTileMap => "QueueTileMap".into(),
-            }
-        }
+    // Problems found while building the BlockKeys lookup off of the TileSet scenes
+    #[derive(Debug, Clone, PartialEq)]
+    enum CellTypeLookupError {
+        MissingKey {
+            source_id: CellIdType,
+            resource_path: GString,
+        },
+        UnknownKey {
+            source_id: CellIdType,
+            resource_path: GString,
+            value: String,
+        },
+        DuplicateKey {
+            key: BlockKeys,
+            source_id: CellIdType,
+            other_source_id: CellIdType,
+        },
     }
-    impl From<BlockUnitsMapType> for i64 {
-        fn from(tile_map_type: BlockUnitsMapType) -> Self {
-            match tile_map_type {
-                BlockUnitsMapType::Undefined => BlockUnitsMapType::Undefined.into(),
-                BlockUnitsMapType::PlayfieldTileMap => BlockUnitsMapType::PlayfieldTileMap.into(),
-                BlockUnitsMapType::QueueTileMap => BlockUnitsMapType::QueueTileMap.into(),
-            }
-        }
-    }
-
-    impl TryFrom<GString> for BlockUnitsMapType {
-        type Error = ();
-
-        fn try_from(godot_string: GString) -> Result<Self, Self::Error> {
-            match godot_string.to_string().as_str() {
-                "Undefined" => Ok(BlockUnitsMapType::Undefined),
-                "PlayfieldTileMap" => Ok(BlockUnitsMapType::PlayfieldTileMap),
-                "QueueTileMap" => Ok(BlockUnitsMapType::QueueTileMap),
-                _ => Err(()),
-            }
-        }
-    }
-    impl TryFrom<i64> for BlockUnitsMapType {
-        type Error = ();
-
-        // NOTE: Unfortunately, we will get into this issue of "lost in translation" symptoms
-        // because C/C++ enums are equivalent to int in terms of sequential order of definition
-        fn try_from(i: i64) -> Result<Self, Self::Error> {
-            match i {
-                0 => Ok(BlockUnitsMapType::Undefined),
-                1 => Ok(BlockUnitsMapType::PlayfieldTileMap),
-                2 => Ok(BlockUnitsMapType::QueueTileMap),
-                _ => Err(()),
+    impl std::fmt::Display for CellTypeLookupError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                CellTypeLookupError::MissingKey { source_id, resource_path } => write!(
+                    f,
+                    "scene '{}' (source_id={}) has no 'block_key' meta-data",
+                    resource_path, source_id
+                ),
+                CellTypeLookupError::UnknownKey { source_id, resource_path, value } => write!(
+                    f,
+                    "scene '{}' (source_id={}) has unknown 'block_key' value '{}'",
+                    resource_path, source_id, value
+                ),
+                CellTypeLookupError::DuplicateKey { key, source_id, other_source_id } => write!(
+                    f,
+                    "source_id={} claims {:?} which is already claimed by source_id={}",
+                    source_id, key, other_source_id
+                ),
             }
         }
     }
@@ -129,12 +98,53 @@ pub mod entry_point {
         // Seems @export_enum is broken, but fortunately, PROPERTY_HINT_ENUM works, so we will use that instead for pulldown selection
         // Unsure how it works interally, but will assume it works similar to .net Enum.TryParse() in which as long as the string is EXACT match to
         // the enum name, it will work.
-        #[var(hint = PROPERTY_HINT_ENUM, hint_string = "Undefined, PlayfieldTileMap, QueueTileMap", usage_flags = [PROPERTY_USAGE_EDITOR])]
+        // NOTE: PROPERTY_USAGE_STORAGE is what gets it saved into the .tscn, without it the map is
+        // always Undefined at runtime no matter what was picked in the Inspector
+        #[var(hint = PROPERTY_HINT_ENUM, hint_string = "Undefined, PlayfieldTileMap, QueueTileMap", usage_flags = [PROPERTY_USAGE_EDITOR, PROPERTY_USAGE_STORAGE])]
         map_type_string: GString,
         map_type_internal: BlockUnitsMapType, // this is the actual value that we will use (I do NOT want to deal with strings)
 
-        cell_map: Vec<Vec<Option<BlockUnitCell>>>, // this is the 2D array of cells (i.e. the map)
+        cell_map: CellMap, // this is the 2D array of cells (i.e. the map)
         cell_type_lookup: BlockUnitCellDictionaryType, // this is the lookup table for the cell types
+
+        // Only used when map_type_internal is QueueTileMap; same seed hands out same pieces, and
+        // with queue_random_orientation the pieces come already turned (the player can still
+        // turn them before placing, see place_from_queue()).  A Playfield that loads a level
+        // replaces the seed (and the weights) with the level's
+        #[export]
+        queue_seed: i64,
+        #[export]
+        queue_random_orientation: bool,
+        tile_queue: Option<TileQueue>,
+
+        // i.e. "res://levels/example_01.txt"; if set (and map is a PlayfieldTileMap), the map is
+        // built from the level file instead of whatever was painted on the TileMap in the Editor
+        #[export]
+        level_path: GString,
+
+        // Playfield only: the run of the level (or of the map painted in the Editor), which owns
+        // the grid, the queue, placement rules, score, clock, stream and undo history (see
+        // internal_primitives::session); every input of the player goes through it, and what it
+        // reports back becomes the signals, so the game plays by the exact same rules as a
+        // replay of it does
+        session: Option<GameSession>,
+
+        // Playfield only: the QueueTileMap (ForBlockUnits) that shows the session's queue (the
+        // pieces place_from_queue() takes)
+        #[export]
+        queue_map_path: NodePath,
+
+        // the player's inputs, with the step of the session they happened at (see replay_save());
+        // only for levels, a map painted in the Editor cannot be replayed
+        recorder: Option<ReplayRecorder>,
+
+        // how many queue pieces after the head get_hints() looks ahead (more is slower, and
+        // anything over internal_primitives::hint::MAX_LOOKAHEAD is taken as that)
+        #[export]
+        hint_lookahead: i32,
+
+        // what was last pushed to the instanced scene tiles (see push_animations())
+        animations: HashMap<GridPosition, CellAnimation>,
     }
 
     // NOTE: (I think) because ITileMap is derived from INode, here, if dealing with just
@@ -151,20 +161,49 @@ pub mod entry_point {
                 map_type_string: BlockUnitsMapType::Undefined.into(),
                 cell_map: Vec::new(),
                 cell_type_lookup: HashMap::new(),
+                queue_seed: 0,
+                queue_random_orientation: false,
+                tile_queue: None,
+                level_path: GString::new(),
+                session: None,
+                queue_map_path: NodePath::default(),
+                recorder: None,
+                hint_lookahead: HintSettings::default().lookahead as i32,
+                animations: HashMap::new(),
             }
             // Q: Build cell_type_lookup dictionary here in init() or in ready()?
         }
 
+        // The session runs as many fixed steps as the frame covers (see GameSession::advance()),
+        // rather than one step of 'delta', so that a replay of this run plays out exactly the same
+        fn process(&mut self, delta: f64) {
+            let events = match self.session.as_mut() {
+                Some(session) => session.advance(delta as f32),
+                None => return,
+            };
+            self.handle_session_events(events);
+        }
+
         fn ready(&mut self) {
             godot_print!("tile_related::MyTileExtension::ready()");
             self.get_singleton_test();
+            // map_type_string is what gets set via Inspector, so resolve the actual value here
+            self.map_type_internal = self
+                .map_type_string
+                .clone()
+                .try_into()
+                .unwrap_or(BlockUnitsMapType::Undefined);
             // build the cell_type_lookup dictionary here IF TileSet is set...
             // if not, we'll need to follow the pattern in which on the time of
             // getting, it will check if dictionary is empty, and if so, build it
             if self.base_mut().get_tileset().is_some() {
                 // build the cell_type_lookup dictionary here IF it's not populated yet
                 if self.cell_type_lookup.is_empty() {
-                    self.cell_type_lookup = self.build_cell_type_lookup();
+                    let (cell_type_lookup, errors) = self.build_cell_type_lookup();
+                    for error in errors {
+                        godot_error!("tile_related::MyTileExtension::ready() - {}", error);
+                    }
+                    self.cell_type_lookup = cell_type_lookup;
                 }
             }
 
@@ -179,6 +218,23 @@ pub mod entry_point {
                 godot_print!("tile_related::MyTileExtension::ready() - TileSet is not set, so no further checks will be done");
                 return;
             }
+            // level file (if any) takes precedence over the cells painted via the Editor
+            if self.map_type_internal == BlockUnitsMapType::PlayfieldTileMap
+                && !self.level_path.is_empty()
+            {
+                let path = self.level_path.clone();
+                let text = FileAccess::get_file_as_string(path.clone()).to_string();
+                match Level::parse(&text) {
+                    Ok(level) => {
+                        self.load_level(level);
+                        return;
+                    }
+                    Err(e) => {
+                        godot_error!("tile_related::MyTileExtension::ready() - failed to load level '{}': {}", path, e);
+                    }
+                }
+            }
+
             // if the map is not set (dimension is 0x0), then we will not do any further checks
             let map_dimension = self.base_mut().get_used_rect().size;
             if map_dimension.x == 0 || map_dimension.y == 0 {
@@ -209,13 +265,15 @@ pub mod entry_point {
                         row.push(None);
                     } else {
                         // TODO: Perhaps verify that this coordinate/position matches used_cells_coords
-                        let pos = Vector2i::new(x, y);
-                        let key = BlockKeys::Undefined;
+                        let pos = GridPosition::new(x, y);
+                        let key = self.key_from_source_id(cell_source_id);
                         row.push(Some(BlockUnitCell {
                             key: key,
                             position: pos,
                             layer: layer,
                             cell_source_id: cell_source_id,
+                            orientation: Orientation::default(), // TileMap has no notion of it, so assume canonical
+                            flags: CellFlags::pre_placed(key), // painted via the Editor, so it counts as pre-placed
                         }));
                     }
                 }
@@ -231,6 +289,17 @@ pub mod entry_point {
                     godot_print!("tile_related::MyTileExtension::ready() - PlayfieldTileMap should have at least 2 rows or 2 columns");
                     return;
                 }
+
+                // the painted map plays by the default rules (without a start, the stream never
+                // runs), with the queue map's own seed
+                let seed = match self.queue_map() {
+                    Some(queue_map) => queue_map.bind().queue_seed as u64,
+                    None => self.queue_seed as u64,
+                };
+                let queue = self.start_queue(seed, TileQueue::default_weights());
+                let cells = std::mem::take(&mut self.cell_map);
+                self.session = Some(GameSession::with_cells(Level::default(), cells, queue));
+                self.orient_scene_tiles();
             } else if self.map_type_internal == BlockUnitsMapType::QueueTileMap {
                 if self.cell_map.len() < 1 {
                     godot_print!("tile_related::MyTileExtension::ready() - QueueTileMap should have at least 1 row or 1 column");
@@ -240,114 +309,1129 @@ pub mod entry_point {
                     godot_print!("tile_related::MyTileExtension::ready() - QueueTileMap should have at least 1 row or 1 column");
                     return;
                 }
+
+                // queue is as big as the map (flattened), and we'll paint the TileMap with it;
+                // unless the Playfield already handed us its level's queue (see reset_queue())
+                if self.tile_queue.is_none() {
+                    self.tile_queue = Some(self.new_queue(
+                        self.queue_seed as u64,
+                        TileQueue::default_weights(),
+                    ));
+                }
+                self.sync_queue_cells();
             }
         }
     }
 
+    #[godot_api]
     impl ForBlockUnits {
-        pub fn get_singleton_test(&self) -> Option<Variant> {
-            // we ASSUME that the singleton ForAutoloadPrimitives is a valid struct
-            // using gawd-awful-typo-error-prone string-based getter:
-            match godot::engine::Engine::singleton()
-                .get_singleton(StringName::from("ForAutoloadPrimitives"))
-            {
-                Some(mut singleton) => {
-                    // Sadly, because we cannot link to the singleton directly, we cannot cast<ForAutoLoadPrimitives>() or
-                    // try_cast<ForAutoLoadPrimitives>() to it, and just "trust" that the method "foo()" exists:
-                    // at least, if we can try_cast, we could have had another match() statement here
-                    // we ASSUME that method ForAutoloadPrimitives::foo() exists!
-                    let call_response = singleton.call("foo".into(), &[]);
-                    Some(call_response)
+        // Signals, so that GDScript (i.e. BlockUnitBase animations, sound, score labels) can
+        // subscribe rather than poll every frame.
+        // NOTE: All of them are emitted deferred (see emit_deferred()), so handlers are free to
+        // call back into this node (i.e. get_cell_key() from cell_placed)
+        // NOTE: 'key' is BlockKeys as int (see BLOCK_KEYS in autoload_globals_tileset.gd), and
+        // sides are passed as Vector2i offsets (i.e. Vector2i(0, -1) is North/up)
+
+        // player tried to place (or clear) on a cell that does not allow it (or too soon after a
+        // replacement); 'reason' is one of "out_of_grid", "start_cell", "goal_cell", "locked",
+        // "obstacle", "not_replaceable", "flow_in_cell", "cooldown"
+        #[signal]
+        fn placement_rejected(position: Vector2i, reason: GString);
+        // player placed a piece onto an empty (Void) cell
+        #[signal]
+        fn cell_placed(position: Vector2i, key: i64);
+        // player placed a piece over an existing one (or cleared it, in which case new_key is Void)
+        #[signal]
+        fn cell_replaced(position: Vector2i, old_key: i64, new_key: i64);
+        // emitted right after cell_replaced: points taken off, and seconds the player now has
+        // to wait before placing again
+        #[signal]
+        fn replacement_penalized(position: Vector2i, points: i64, delay_seconds: f64);
+        // stream entered the cell through 'from_side'
+        #[signal]
+        fn flow_entered_cell(position: Vector2i, from_side: Vector2i);
+        // stream left the cell through 'towards_side'
+        #[signal]
+        fn flow_exited_cell(position: Vector2i, towards_side: Vector2i);
+        // stream poured out of the cell into nothing (void, hole or off the grid)
+        #[signal]
+        fn flow_spilled(position: Vector2i, towards_side: Vector2i);
+        // stream is stuck (blocked by an obstacle, wrong orientation, or already filled pipe);
+        // towards_side is Vector2i(0, 0) if the cell itself has no way out
+        #[signal]
+        fn flow_blocked(position: Vector2i, towards_side: Vector2i);
+        // stream made it into a goal; 'path' is every cell filled so far, in order
+        #[signal]
+        fn route_completed(goal: Vector2i, path: Array<Vector2i>);
+        // stream ran into a join (through 'from_side') another stream already went through,
+        // and goes on as part of that one
+        #[signal]
+        fn flow_merged(position: Vector2i, from_side: Vector2i);
+        // goal now holds the volume the level requires of it
+        #[signal]
+        fn goal_filled(goal: Vector2i, volume: f64);
+        // countdown is over, the stream begins to flow
+        #[signal]
+        fn flow_started();
+        // stream has ended (spilled, blocked, or all of it made it into goals), with the final
+        // score (unused pieces already taken off) and whether the minimum length was reached
+        #[signal]
+        fn level_finished(won: bool, score: i64);
+        // the cell went back to what it was before the last placement ('key' is what it has now)
+        #[signal]
+        fn placement_undone(position: Vector2i, key: i64);
+        #[signal]
+        fn placement_redone(position: Vector2i, key: i64);
+        // 'reason' is one of "nothing_to_undo", "nothing_to_redo", "budget_exhausted", "flow_reached"
+        #[signal]
+        fn undo_rejected(reason: GString);
+
+        // Writes what was played so far (level, queue and every input) as a replay file, i.e.
+        // "user://replays/last.txt" (see internal_primitives::replay); false if no level is
+        // loaded or the file cannot be written
+        #[func]
+        fn replay_save(&mut self, path: GString) -> bool {
+            let text = match (self.recorder.as_ref(), self.session.as_ref()) {
+                (Some(recorder), Some(session)) => recorder.to_replay(session.step()).to_text(),
+                _ => {
+                    godot_error!("tile_related::MyTileExtension::replay_save() - no level loaded");
+                    return false;
+                }
+            };
+            match FileAccess::open(path.clone(), ModeFlags::WRITE) {
+                Some(mut file) => {
+                    file.store_string(text.into());
+                    file.close();
+                    true
+                }
+                None => {
+                    godot_error!("tile_related::MyTileExtension::replay_save() - cannot write '{}'", path);
+                    false
+                }
+            }
+        }
+
+        // pops the head of the queue (and refills the tail), returns a Dictionary with "key"
+        // (BlockKeys as int, see BLOCK_KEYS in autoload_globals_tileset.gd) and "orientation"
+        // (int, same as get_cell_orientation()); key is Undefined if this is not a QueueTileMap
+        // NOTE: A queue the Playfield takes its pieces from belongs to the Playfield's session,
+        // this map only shows it (and gets repainted on the next placement), so place through
+        // the Playfield's place_from_queue() rather than popping here
+        #[func]
+        fn queue_get_head(&mut self) -> Dictionary {
+            let head = match self.tile_queue.as_mut() {
+                Some(queue) => queue.get_head(),
+                None => {
+                    godot_error!("tile_related::MyTileExtension::queue_get_head() - not a QueueTileMap (or not ready yet)");
+                    OrientedBlock::from(BlockKeys::Undefined)
+                }
+            };
+            self.sync_queue_cells();
+            Self::block_to_dictionary(head)
+        }
+
+        // returns up to 'count' pieces (same Dictionary as queue_get_head()) from the head
+        // without popping
+        #[func]
+        fn queue_peek(&self, count: i64) -> Array<Dictionary> {
+            let mut ret = Array::new();
+            if let Some(queue) = self.tile_queue.as_ref() {
+                for block in queue.peek(count.max(0) as usize) {
+                    ret.push(Self::block_to_dictionary(block));
+                }
+            }
+            ret
+        }
+
+        #[func]
+        fn queue_get_seed(&self) -> i64 {
+            self.queue_seed
+        }
+
+        // Script-facing grid API: GDScript only deals with BlockKeys (as int, see BLOCK_KEYS in
+        // autoload_globals_tileset.gd) and grid positions, never with TileSet source_id; both the
+        // cell_map and the TileMap cells are kept in sync on every write.
+
+        // BlockKeys (as int) at position; Undefined if out of the grid (or a hole)
+        #[func]
+        fn get_cell_key(&self, position: Vector2i) -> i64 {
+            self.cell_ref(position)
+                .map(|cell| cell.key)
+                .unwrap_or(BlockKeys::Undefined)
+                .into()
+        }
+
+        // Orientation packed as int (see internal_primitives::orientation), 0 if out of the grid
+        #[func]
+        fn get_cell_orientation(&self, position: Vector2i) -> i64 {
+            self.cell_ref(position)
+                .map(|cell| cell.orientation)
+                .unwrap_or_default()
+                .into()
+        }
+
+        // returns false (and leaves the cell as-is) if position, key or orientation is invalid,
+        // or if the cell does not allow placing on it (see placement_rejected signal)
+        #[func]
+        fn set_cell_key(&mut self, position: Vector2i, key: i64, orientation: i64) -> bool {
+            let (key, orientation): (BlockKeys, Orientation) =
+                match (key.try_into(), orientation.try_into()) {
+                    (Ok(key), Ok(orientation)) => (key, orientation),
+                    _ => {
+                        godot_error!("tile_related::MyTileExtension::set_cell_key() - invalid key={} or orientation={}", key, orientation);
+                        return false;
+                    }
+                };
+            if !self.has_scene(key) {
+                return false;
+            }
+            self.apply_input(SessionInput::Set {
+                position: position.into(),
+                block: OrientedBlock::new(key, orientation),
+            })
+        }
+
+        // Takes the head of the queue (shown on the QueueTileMap at queue_map_path) and places it
+        // at position; unlike set_cell_key(), undo() will also hand the piece back to the queue
+        #[func]
+        fn place_from_queue(&mut self, position: Vector2i, orientation: i64) -> bool {
+            let orientation: Orientation = match orientation.try_into() {
+                Ok(orientation) => orientation,
+                Err(_) => {
+                    godot_error!("tile_related::MyTileExtension::place_from_queue() - invalid orientation={}", orientation);
+                    return false;
+                }
+            };
+            if self.queue_map().is_none() {
+                godot_error!("tile_related::MyTileExtension::place_from_queue() - queue_map_path does not point to a QueueTileMap");
+                return false;
+            }
+            let head = self
+                .session
+                .as_ref()
+                .and_then(|session| session.queue().peek(1).first().copied());
+            match head {
+                Some(head) if self.has_scene(head.key) => self.apply_input(SessionInput::Place {
+                    position: position.into(),
+                    orientation,
+                }),
+                _ => false,
+            }
+        }
+
+        // same as set_cell_key() with Void
+        #[func]
+        fn clear_cell(&mut self, position: Vector2i) -> bool {
+            if !self.has_scene(BlockKeys::Void) {
+                return false;
+            }
+            self.apply_input(SessionInput::Set {
+                position: position.into(),
+                block: OrientedBlock::from(BlockKeys::Void),
+            })
+        }
+
+        // locked (pre-placed by the level) cells cannot be changed via set_cell_key()/clear_cell()
+        #[func]
+        fn is_locked(&self, position: Vector2i) -> bool {
+            self.cell_ref(position)
+                .map(|cell| cell.flags.locked)
+                .unwrap_or(false)
+        }
+
+        // CellFlags packed as int (see internal_primitives CellFlags), 0 if out of the grid
+        #[func]
+        fn get_cell_flags(&self, position: Vector2i) -> i64 {
+            self.cell_ref(position)
+                .map(|cell| cell.flags.into())
+                .unwrap_or(0)
+        }
+
+        // why a piece cannot be placed at position (same reasons as placement_rejected signal),
+        // or empty if it can; i.e. for hover/highlight before the player clicks
+        #[func]
+        fn get_placement_error(&self, position: Vector2i) -> GString {
+            let result = match self.session.as_ref() {
+                Some(session) => session.check_placement(position.into()),
+                None => Err(PlacementError::OutOfGrid),
+            };
+            match result {
+                Ok(_) => GString::new(),
+                Err(e) => e.as_str().into(),
+            }
+        }
+
+        // total points taken off so far for replacing placed pieces
+        #[func]
+        fn get_penalty_points(&self) -> i64 {
+            self.session
+                .as_ref()
+                .map(|session| session.rules().penalty_points() as i64)
+                .unwrap_or(0)
+        }
+
+        // seconds left before the player can place again (after a replacement)
+        #[func]
+        fn get_placement_cooldown(&self) -> f64 {
+            self.session
+                .as_ref()
+                .map(|session| session.rules().cooldown_seconds() as f64)
+                .unwrap_or(0.0)
+        }
+
+        // the neighbours that are inside the grid, up to 4 on the square/isometric grid and up
+        // to 6 on the hexagons (which cells those are depends on the level's topology, see
+        // internal_primitives::topology); NOTE: two of the six hexagon sides have no port
+        #[func]
+        fn neighbours(&self, position: Vector2i) -> Array<Vector2i> {
+            let mut ret = Array::new();
+            let topology = self.topology();
+            for side in topology.sides() {
+                let neighbour: Vector2i = topology.neighbour(position.into(), *side).into();
+                if self.cell_ref(neighbour).is_some() {
+                    ret.push(neighbour);
+                }
+            }
+            ret
+        }
+
+        // Way 'side' (same Vector2i offsets as the signals, i.e. Vector2i(0, -1) is North) faces
+        // on screen for the level's topology, in degrees clockwise from up; i.e. North faces
+        // up-right (45) on an isometric grid, and (30) on the hexagons.  0 if 'side' is not one
+        // of the four
+        #[func]
+        fn get_side_angle(&self, side: Vector2i) -> i64 {
+            let topology = self.topology();
+            Direction::ALL
+                .into_iter()
+                .find(|direction| direction.offset() == (side.x, side.y))
+                .map(|direction| topology.side_angle_degrees(topology.port_side(direction)) as i64)
+                .unwrap_or(0)
+        }
+
+        // (width, height) of cell_map, (0, 0) if not ready
+        #[func]
+        fn dimensions(&self) -> Vector2i {
+            let width = self.cells().len() as i32;
+            let height = self.cells().first().map(|row| row.len()).unwrap_or(0) as i32;
+            Vector2i::new(width, height)
+        }
+
+        // What the streams left in the cell (see internal_primitives::flow::CellFlow), all 0
+        // (and not flowing) before the stream starts and for cells it never reached:
+        // fill level from 0.0 to 1.0 (less than 1.0 while the goo is on its way across, or
+        // for a divided stream)
+        #[func]
+        fn get_cell_fill(&self, position: Vector2i) -> f64 {
+            self.cell_flow(position).fill as f64
+        }
+
+        // strength of the strongest stream that went through, 1.0 being one start's worth
+        #[func]
+        fn get_cell_pressure(&self, position: Vector2i) -> f64 {
+            self.cell_flow(position).pressure as f64
+        }
+
+        // total volume that went into the cell
+        #[func]
+        fn get_cell_volume(&self, position: Vector2i) -> f64 {
+            self.cell_flow(position).volume as f64
+        }
+
+        // true while the stream is in the cell (it entered on the last flow tick)
+        #[func]
+        fn is_cell_flowing(&self, position: Vector2i) -> bool {
+            self.cell_flow(position).flowing
+        }
+
+        // Game clock controls; the clock is created (and its countdown starts) when a level is loaded
+        #[func]
+        fn clock_pause(&mut self) {
+            self.apply_input(SessionInput::Pause);
+        }
+
+        #[func]
+        fn clock_resume(&mut self) {
+            self.apply_input(SessionInput::Resume);
+        }
+
+        // skip the rest of the countdown and speed up the stream until the end
+        #[func]
+        fn clock_fast_forward(&mut self) {
+            self.apply_input(SessionInput::FastForward);
+        }
+
+        #[func]
+        fn is_clock_paused(&self) -> bool {
+            self.session
+                .as_ref()
+                .map(|session| session.clock().is_paused())
+                .unwrap_or(false)
+        }
+
+        // seconds left before the stream begins to flow (0 once it is flowing)
+        #[func]
+        fn get_countdown_left(&self) -> f64 {
+            self.session
+                .as_ref()
+                .map(|session| session.clock().countdown_left() as f64)
+                .unwrap_or(0.0)
+        }
+
+        // current score (can go negative, i.e. after a few replacements)
+        #[func]
+        fn get_score(&self) -> i64 {
+            self.session
+                .as_ref()
+                .map(|session| session.score().points() as i64)
+                .unwrap_or(0)
+        }
+
+        // Undo/redo of the placements (see internal_primitives::history); points and delays
+        // paid for a replacement are not given back.  Both return false (and emit undo_rejected)
+        // if there is nothing to undo/redo, the level's undo budget is spent, or the stream
+        // already reached the cell
+        #[func]
+        fn undo(&mut self) -> bool {
+            self.apply_input(SessionInput::Undo)
+        }
+
+        #[func]
+        fn redo(&mut self) -> bool {
+            self.apply_input(SessionInput::Redo)
+        }
+
+        #[func]
+        fn can_undo(&self) -> bool {
+            self.session
+                .as_ref()
+                .map(|session| session.history().can_undo())
+                .unwrap_or(false)
+        }
+
+        #[func]
+        fn can_redo(&self) -> bool {
+            self.session
+                .as_ref()
+                .map(|session| session.history().can_redo())
+                .unwrap_or(false)
+        }
+
+        // -1 if the level does not limit undos
+        #[func]
+        fn get_undos_left(&self) -> i64 {
+            self.session
+                .as_ref()
+                .and_then(|session| session.history().undos_left())
+                .map(|left| left as i64)
+                .unwrap_or(-1)
+        }
+
+        // Up to 'count' cells to put the head of the queue (shown on the QueueTileMap at
+        // queue_map_path) on, best first, over every stream (each start before the stream
+        // begins, each branch of a split after; see internal_primitives::hint); each is a
+        // Dictionary with "head" (Vector2i, the cell the stream the hint is for is leaving),
+        // "position" (Vector2i), "orientation" (int, same as get_cell_orientation()), "reach"
+        // (cells that stream gets to) and "extension" (cells more than without the piece).
+        // Empty if no cell gets any stream further (or there is no level/queue)
+        #[func]
+        fn get_hints(&self, count: i64) -> Array<Dictionary> {
+            let mut ret = Array::new();
+            let session = match self.session.as_ref() {
+                Some(session) => session,
+                None => {
+                    godot_error!("tile_related::MyTileExtension::get_hints() - no level loaded");
+                    return ret;
+                }
+            };
+            // no heads left once the stream has ended
+            let heads: Vec<FlowHead> = match session.flow() {
+                Some(flow) => flow.heads(),
+                None => session
+                    .level()
+                    .starts
+                    .iter()
+                    .map(|start| FlowHead {
+                        position: start.position,
+                        exit: start.exit,
+                    })
+                    .collect(),
+            };
+            let settings = HintSettings {
+                lookahead: (self.hint_lookahead.max(0) as usize).min(MAX_LOOKAHEAD),
+                topology: self.topology(),
+            };
+            let queue: Vec<BlockKeys> = session
+                .queue()
+                .peek(settings.lookahead + 1)
+                .iter()
+                .map(|block| block.key)
+                .collect();
+            let hints =
+                rank_cells_for_heads(session.cells(), &heads, session.flow(), &queue, &settings);
+            for hint in hints.into_iter().take(count.max(0) as usize) {
+                let mut dictionary = Dictionary::new();
+                dictionary.set("head", Vector2i::from(hint.head.position));
+                dictionary.set("position", Vector2i::from(hint.position));
+                dictionary.set("orientation", i64::from(hint.orientation));
+                dictionary.set("reach", hint.reach as i64);
+                dictionary.set("extension", hint.extension as i64);
+                ret.push(dictionary);
+            }
+            ret
+        }
+
+        fn cell_ref(&self, position: Vector2i) -> Option<&BlockUnitCell> {
+            cell_at(self.cells(), position.into())
+        }
+
+        // the session's grid on the Playfield, cell_map on any other map
+        fn cells(&self) -> &CellMap {
+            match self.session.as_ref() {
+                Some(session) => session.cells(),
+                None => &self.cell_map,
+            }
+        }
+
+        // false (and reports it) if the TileSet has no scene to paint 'key' with
+        fn has_scene(&self, key: BlockKeys) -> bool {
+            if self.cell_type_lookup.contains_key(&key) {
+                return true;
+            }
+            godot_error!("tile_related::MyTileExtension::has_scene() - no source_id for {:?}", key);
+            false
+        }
+
+        // Records the input (at the step the session is on) and hands it to the session; true if
+        // it changed the grid (or the clock), false if it got rejected
+        fn apply_input(&mut self, input: SessionInput) -> bool {
+            let events = match self.session.as_mut() {
+                Some(session) => {
+                    if let Some(recorder) = self.recorder.as_mut() {
+                        recorder.record(session.step(), input);
+                    }
+                    session.apply(input)
                 }
                 None => {
-                    //... do something, probably the best thing to do is complain that you'd need to make sure the autoload_primitives.gdextension exist
-                    godot_error!("tile_related::MyTileExtension::get_singleton_test() - ForAutoloadPrimitives singleton via autoload_primitives.gdextension is not loaded!");
-                    None
+                    godot_error!("tile_related::MyTileExtension::apply_input() - not a Playfield (or not ready yet)");
+                    return false;
+                }
+            };
+            let rejected = events.iter().any(|event| {
+                matches!(
+                    event,
+                    SessionEvent::Rejected { .. } | SessionEvent::UndoRejected(_)
+                )
+            });
+            self.handle_session_events(events);
+            !rejected
+        }
+
+        // Repaints whatever the session changed, and turns what it reports into the signals
+        fn handle_session_events(&mut self, events: Vec<SessionEvent>) {
+            let mut flowed = false;
+            for event in events {
+                match event {
+                    SessionEvent::Placed {
+                        position,
+                        block,
+                        outcome,
+                    } => {
+                        let position: Vector2i = position.into();
+                        self.repaint_cell(position);
+                        self.orient_scene_tiles();
+                        self.sync_queue_map();
+                        self.emit_placed(position, block, outcome);
+                    }
+                    SessionEvent::Rejected { position, error } => {
+                        let position: Vector2i = position.into();
+                        let reason: GString = error.as_str().into();
+                        self.emit_deferred(
+                            "placement_rejected",
+                            &[position.to_variant(), reason.to_variant()],
+                        );
+                    }
+                    SessionEvent::Undone(position) | SessionEvent::Redone(position) => {
+                        let position: Vector2i = position.into();
+                        self.repaint_cell(position);
+                        self.orient_scene_tiles();
+                        self.sync_queue_map();
+                        let signal = match event {
+                            SessionEvent::Undone(_) => "placement_undone",
+                            _ => "placement_redone",
+                        };
+                        let key: i64 = self
+                            .cell_ref(position)
+                            .map(|cell| cell.key)
+                            .unwrap_or(BlockKeys::Undefined)
+                            .into();
+                        self.emit_deferred(signal, &[position.to_variant(), key.to_variant()]);
+                    }
+                    SessionEvent::UndoRejected(e) => {
+                        let reason: GString = e.as_str().into();
+                        self.emit_deferred("undo_rejected", &[reason.to_variant()]);
+                    }
+                    SessionEvent::FlowStarted => {
+                        self.animations.clear();
+                        self.emit_deferred("flow_started", &[]);
+                    }
+                    SessionEvent::Flow(event) => {
+                        flowed = true;
+                        let path: Array<Vector2i> = self
+                            .session
+                            .as_ref()
+                            .and_then(|session| session.flow())
+                            .map(|flow| {
+                                flow.filled_cells()
+                                    .iter()
+                                    .map(|position| Vector2i::from(*position))
+                                    .collect()
+                            })
+                            .unwrap_or_else(Array::new);
+                        self.emit_flow_event(event, &path);
+                    }
+                    SessionEvent::Finished(summary) => {
+                        self.emit_deferred(
+                            "level_finished",
+                            &[
+                                summary.won.to_variant(),
+                                (summary.total as i64).to_variant(),
+                            ],
+                        );
+                    }
+                }
+            }
+            if flowed {
+                self.push_animations();
+            }
+        }
+
+        // cell_placed/cell_replaced (and replacement_penalized) for a placement of the player
+        fn emit_placed(
+            &mut self,
+            position: Vector2i,
+            block: OrientedBlock,
+            outcome: PlacementOutcome,
+        ) {
+            let new_key: i64 = block.key.into();
+            match outcome {
+                PlacementOutcome::Placed => {
+                    if block.key != BlockKeys::Void {
+                        self.emit_deferred(
+                            "cell_placed",
+                            &[position.to_variant(), new_key.to_variant()],
+                        );
+                    }
+                }
+                PlacementOutcome::Replaced {
+                    old_key,
+                    delay_seconds,
+                    point_penalty,
+                } => {
+                    let old_key: i64 = old_key.into();
+                    self.emit_deferred(
+                        "cell_replaced",
+                        &[
+                            position.to_variant(),
+                            old_key.to_variant(),
+                            new_key.to_variant(),
+                        ],
+                    );
+                    self.emit_deferred(
+                        "replacement_penalized",
+                        &[
+                            position.to_variant(),
+                            (point_penalty as i64).to_variant(),
+                            (delay_seconds as f64).to_variant(),
+                        ],
+                    );
+                }
+            }
+        }
+
+        // Signals go out once the call that caused them is over (at idle time, in the order they
+        // were emitted) rather than right away: this node is still bound (&mut self) while in
+        // here, and a handler calling any #[func] on it would panic on the second bind
+        fn emit_deferred(&mut self, signal: &str, args: &[Variant]) {
+            let mut call_args = vec![StringName::from(signal).to_variant()];
+            call_args.extend_from_slice(args);
+            self.base_mut().call_deferred("emit_signal".into(), &call_args);
+        }
+
+        fn emit_flow_event(&mut self, event: FlowEvent, path: &Array<Vector2i>) {
+            let side_to_vector = |side: Direction| {
+                let (x, y) = side.offset();
+                Vector2i::new(x, y)
+            };
+            let (signal, position, second) = match event {
+                FlowEvent::Entered { position, from } => {
+                    ("flow_entered_cell", position, side_to_vector(from).to_variant())
+                }
+                FlowEvent::Exited { position, towards } => {
+                    ("flow_exited_cell", position, side_to_vector(towards).to_variant())
+                }
+                FlowEvent::Spilled { position, towards } => {
+                    ("flow_spilled", position, side_to_vector(towards).to_variant())
+                }
+                FlowEvent::DeadEnd { position, towards } => {
+                    let towards = towards.map(side_to_vector).unwrap_or(Vector2i::new(0, 0));
+                    ("flow_blocked", position, towards.to_variant())
+                }
+                FlowEvent::ReachedGoal { position, .. } => {
+                    ("route_completed", position, path.to_variant())
+                }
+                FlowEvent::Merged { position, from } => {
+                    ("flow_merged", position, side_to_vector(from).to_variant())
+                }
+                FlowEvent::GoalFilled { position, volume } => {
+                    ("goal_filled", position, (volume as f64).to_variant())
+                }
+            };
+            let position: Vector2i = position.into();
+            self.emit_deferred(signal, &[position.to_variant(), second]);
+        }
+
+        // Updates both cell_map and the TileMap cell (QueueTileMap only, the Playfield's grid
+        // belongs to its session), false if position is not on the grid or the TileSet has no
+        // scene for the key
+        fn write_cell(&mut self, position: Vector2i, block: OrientedBlock) -> bool {
+            let layer = match self.cell_ref(position) {
+                Some(cell) => cell.layer,
+                None => return false,
+            };
+            let (source_id, tile_id) = match self.cell_type_lookup.get(&block.key) {
+                Some(value) => (value.source_id, value.tile_id),
+                None => {
+                    godot_error!("tile_related::MyTileExtension::write_cell() - no source_id for {:?}", block.key);
+                    return false;
+                }
+            };
+            self.set_scene_cell(layer, position, source_id, tile_id);
+            if let Some(Some(cell)) = self
+                .cell_map
+                .get_mut(position.x as usize)
+                .and_then(|row| row.get_mut(position.y as usize))
+            {
+                cell.place(block);
+                cell.cell_source_id = source_id;
+            }
+            true
+        }
+
+        // usage of TileSetScenesCollectionSource requires atlas position to always be (0, 0), and
+        // the scene is picked by the alternative tile (its scene tile id, which the TileSet
+        // Editor hands out starting from 1, not 0)
+        fn set_scene_cell(
+            &mut self,
+            layer: LayerType,
+            position: Vector2i,
+            source_id: CellIdType,
+            tile_id: CellIdType,
+        ) {
+            self.base_mut()
+                .set_cell_ex(layer, position)
+                .source_id(source_id)
+                .atlas_coords(Vector2i::new(0, 0))
+                .alternative_tile(tile_id)
+                .done();
+        }
+
+        // the level's, or Square if the map was painted in the Editor
+        fn topology(&self) -> GridTopology {
+            self.session
+                .as_ref()
+                .map(|session| session.level().topology)
+                .unwrap_or_default()
+        }
+
+        // The TileSet has to be laid out the way the topology expects (see
+        // internal_primitives::topology), or the cells the simulation takes as neighbours are not
+        // the ones drawn next to each other; the TileSet is a resource (shared with every other
+        // TileMap using it), so it is only checked here, setting it up is left to the Editor
+        fn check_tileset_topology(&self, topology: GridTopology) -> Result<(), String> {
+            let tileset = match self.base().get_tileset() {
+                Some(tileset) => tileset,
+                None => return Ok(()),
+            };
+            let shape = tileset.get_tile_shape();
+            let layout = tileset.get_tile_layout();
+            let horizontal = tileset.get_tile_offset_axis() == TileOffsetAxis::HORIZONTAL;
+            let (matches, expected) = match topology {
+                GridTopology::Square => (shape == TileShape::SQUARE, "Square"),
+                GridTopology::IsoDiamond => (
+                    shape == TileShape::ISOMETRIC && layout == TileLayout::DIAMOND_DOWN && horizontal,
+                    "Isometric, Diamond Down layout, Horizontal offset axis",
+                ),
+                GridTopology::HexOffset => (
+                    shape == TileShape::HEXAGON && layout == TileLayout::STACKED && horizontal,
+                    "Hexagon, Stacked layout, Horizontal offset axis",
+                ),
+                GridTopology::HexAxial => (
+                    shape == TileShape::HEXAGON && layout == TileLayout::STAIRS_RIGHT && horizontal,
+                    "Hexagon, Stairs Right layout, Horizontal offset axis",
+                ),
+            };
+            match matches {
+                true => Ok(()),
+                false => Err(format!(
+                    "topology '{}' needs a {} TileSet, but it is {:?} ({:?}, {:?})",
+                    topology.as_str(),
+                    expected,
+                    shape,
+                    layout,
+                    tileset.get_tile_offset_axis()
+                )),
+            }
+        }
+
+        fn cell_flow(&self, position: Vector2i) -> CellFlow {
+            self.session
+                .as_ref()
+                .and_then(|session| session.flow())
+                .map(|flow| flow.cell_flow(position.into()))
+                .unwrap_or_default()
+        }
+
+        // the ForBlockUnits at queue_map_path, if set (and if it is one)
+        fn queue_map(&self) -> Option<Gd<ForBlockUnits>> {
+            if self.queue_map_path.is_empty() {
+                return None;
+            }
+            self.base()
+                .try_get_node_as::<ForBlockUnits>(self.queue_map_path.clone())
+        }
+
+        // QueueTileMap side of load_level(): starts over with the level's seed and weights (so the
+        // player gets the same pieces the level was checked against, see level_solver); may run
+        // before this map's own ready(), in which case the cells get painted from there
+        fn reset_queue(&mut self, seed: u64, weights: TileQueueWeights) {
+            self.queue_seed = seed as i64;
+            self.tile_queue = Some(self.new_queue(seed, weights));
+            self.sync_queue_cells();
+        }
+
+        // as big as the map (flattened), so that each cell shows one piece
+        fn new_queue(&self, seed: u64, weights: TileQueueWeights) -> TileQueue {
+            let size = self.base().get_used_rect().size;
+            let capacity = (size.x.max(0) * size.y.max(0)) as usize;
+            match self.queue_random_orientation {
+                true => TileQueue::new_oriented(capacity, seed, weights),
+                false => TileQueue::new(capacity, seed, weights),
+            }
+        }
+
+        // QueueTileMap side of sync_queue_map()
+        fn show_queue(&mut self, queue: TileQueue) {
+            self.tile_queue = Some(queue);
+            self.sync_queue_cells();
+        }
+
+        // Repaints the TileMap cell from the session's grid (i.e. after a placement or undo())
+        fn repaint_cell(&mut self, position: Vector2i) {
+            let (layer, key) = match self.cell_ref(position) {
+                Some(cell) => (cell.layer, cell.key),
+                None => return,
+            };
+            let (source_id, tile_id) = match self.cell_type_lookup.get(&key) {
+                Some(value) => (value.source_id, value.tile_id),
+                None => {
+                    godot_error!("tile_related::MyTileExtension::repaint_cell() - no source_id for {:?}", key);
+                    return;
                 }
+            };
+            self.set_scene_cell(layer, position, source_id, tile_id);
+        }
+
+        // Shows the session's queue on the QueueTileMap (after a piece was taken off of it, or
+        // handed back by undo())
+        fn sync_queue_map(&mut self) {
+            let queue = match self.session.as_ref() {
+                Some(session) => session.queue().clone(),
+                None => return,
+            };
+            if let Some(mut queue_map) = self.queue_map() {
+                queue_map.bind_mut().show_queue(queue);
             }
         }
 
-        fn build_cell_type_lookup(&mut self) -> BlockUnitCellDictionaryType {
+        // The queue the session starts with: the QueueTileMap's, started over with 'seed' and
+        // 'weights' (so it is as big as the map shows), or one without a map to show it on
+        fn start_queue(&self, seed: u64, weights: TileQueueWeights) -> TileQueue {
+            if let Some(mut queue_map) = self.queue_map() {
+                let mut queue_map = queue_map.bind_mut();
+                queue_map.reset_queue(seed, weights.clone());
+                if let Some(queue) = queue_map.tile_queue.clone() {
+                    return queue;
+                }
+            }
+            TileQueue::new(0, seed, weights)
+        }
+
+        // Starts a session on the level (and repaints the TileMap from it), instead of reading
+        // get_used_cells()
+        fn load_level(&mut self, level: Level) {
+            let layer = 0;
+            // loaded anyway, but it will not look like what gets simulated
+            if let Err(e) = self.check_tileset_topology(level.topology) {
+                godot_error!("tile_related::MyTileExtension::load_level() - {}", e);
+            }
+            // the queue starts over with the level's settings (so the player gets the same pieces
+            // the level was checked against, see level_solver), and that (before anything is
+            // taken off of it) is the queue the replay is recorded against
+            let queue = self.start_queue(level.queue_seed, level.queue_weights.clone());
+            self.recorder = Some(ReplayRecorder::new(level.clone(), &queue));
+            godot_print!(
+                "tile_related::MyTileExtension::load_level() - loaded '{}' ({} x {})",
+                level.name,
+                level.width,
+                level.height
+            );
+            self.session = Some(GameSession::new(level, queue));
+            self.animations.clear();
+            // level already knows the keys and flags (start, goal, locked...), only the TileSet
+            // source_id has to be looked up here
+            let mut painted: Vec<(Vector2i, CellIdType, CellIdType)> = Vec::new();
+            for cell in self.cells().iter().flatten().flatten() {
+                match self.cell_type_lookup.get(&cell.key) {
+                    Some(value) => {
+                        painted.push((cell.position.into(), value.source_id, value.tile_id))
+                    }
+                    // towers are (for now) not part of the playfield TileSet, the cell is
+                    // left unpainted and the tower scene is expected to be placed on top
+                    None if cell.key == BlockKeys::Tower => {}
+                    None => {
+                        godot_error!("tile_related::MyTileExtension::load_level() - no source_id for {:?}", cell.key);
+                    }
+                }
+            }
+            for (position, source_id, tile_id) in painted {
+                self.set_scene_cell(layer, position, source_id, tile_id);
+            }
+            self.orient_scene_tiles();
+        }
+
+        // Tells the instanced scene tiles whose AnimationState changed since the last time (see
+        // internal_primitives::animation) what to play, via BlockUnitBase.cb_flow_state(state,
+        // entry_side, reversed); entry_side is Vector2i(0, 0) if the stream never got there.
+        // NOTE: TileMap adds the scenes of TileSetScenesCollectionSource as its own children,
+        // positioned at map_to_local() of their cell, which is the only way back to the cell
+        fn push_animations(&mut self) {
+            let flow = match self.session.as_ref().and_then(|session| session.flow()) {
+                Some(flow) => flow,
+                None => return,
+            };
+            let mut changed: HashMap<GridPosition, CellAnimation> = HashMap::new();
+            for cell in self.cells().iter().flatten().flatten() {
+                let animation = cell_animation(cell, &flow.cell_flow(cell.position));
+                let last = self.animations.get(&cell.position).copied().unwrap_or_default();
+                if animation != last {
+                    changed.insert(cell.position, animation);
+                }
+            }
+            if changed.is_empty() {
+                return;
+            }
+            for (cell, mut child) in self.scene_tiles() {
+                let animation = match changed.get(&cell) {
+                    Some(animation) => *animation,
+                    None => continue,
+                };
+                if !child.has_method("cb_flow_state".into()) {
+                    continue;
+                }
+                let entry_side = animation
+                    .entry
+                    .map(|side| {
+                        let (x, y) = side.offset();
+                        Vector2i::new(x, y)
+                    })
+                    .unwrap_or(Vector2i::new(0, 0));
+                // deferred, same as the signals (see emit_deferred())
+                child.call_deferred(
+                    "cb_flow_state".into(),
+                    &[
+                        i64::from(animation.state).to_variant(),
+                        entry_side.to_variant(),
+                        animation.reversed.to_variant(),
+                    ],
+                );
+            }
+            self.animations.extend(changed);
+        }
+
+        // Repaints the QueueTileMap cells from the queue, head first (x-major, same order
+        // as TileMap_NextTiles.gd peek())
+        fn sync_queue_cells(&mut self) {
+            let blocks = match self.tile_queue.as_ref() {
+                Some(queue) => queue.as_slice().to_vec(),
+                None => return,
+            };
+            let positions: Vec<Vector2i> = self
+                .cell_map
+                .iter()
+                .flatten()
+                .flatten()
+                .map(|cell| cell.position.into())
+                .collect();
+            for (position, block) in positions.into_iter().zip(blocks) {
+                self.write_cell(position, block);
+            }
+            self.orient_scene_tiles();
+        }
+
+        fn block_to_dictionary(block: OrientedBlock) -> Dictionary {
+            let mut dictionary = Dictionary::new();
+            dictionary.set("key", i64::from(block.key));
+            dictionary.set("orientation", i64::from(block.orientation));
+            dictionary
+        }
+
+        // The instanced scene tiles (children of the TileMap, see push_animations()) with the
+        // cell they are on
+        fn scene_tiles(&self) -> Vec<(GridPosition, Gd<Node>)> {
+            let children = self.base().get_children();
+            let mut tiles = Vec::new();
+            for index in 0..children.len() {
+                let child = children.get(index);
+                let position = match child.get("position".into()).try_to::<Vector2>() {
+                    Ok(position) => position,
+                    Err(_) => continue, // not a Node2D, so not a scene tile
+                };
+                tiles.push((self.base().local_to_map(position).into(), child));
+            }
+            tiles
+        }
+
+        // TileSetScenesCollectionSource cannot transform its tiles (atlas is always (0, 0)), so
+        // the orientation of each cell is applied to its instanced scene instead: mirrored first
+        // (scale.x = -1), then rotated clockwise, same order as internal_primitives::orientation
+        // NOTE: TileMap only instances the scenes on its next internal update, hence the
+        // update_internals() so that freshly painted cells already have theirs
+        fn orient_scene_tiles(&mut self) {
+            self.base_mut().update_internals();
+            for (position, mut child) in self.scene_tiles() {
+                let orientation = match cell_at(self.cells(), position) {
+                    Some(cell) => cell.orientation,
+                    None => continue,
+                };
+                child.set(
+                    "rotation_degrees".into(),
+                    (orientation.rotation_degrees() as f32).to_variant(),
+                );
+                child.set(
+                    "scale".into(),
+                    Vector2::new(orientation.scale_x() as f32, 1.0).to_variant(),
+                );
+            }
+        }
+
+        // Reads the 'block_key' meta-data (BlockKeys name as String, i.e. "Router1Corner") off the
+        // root node of every scene in the TileSetScenesCollectionSource(s), so that we can look up
+        // source_id/scene/resource_path by BlockKeys instead of hard-coding source_id (which changes
+        // whenever the TileSet gets edited)
+        // NOTE: Scenes that are missing the meta-data, or that claim a BlockKeys that another scene
+        // already claimed, are reported as errors and left out (first one wins) rather than panic
+        fn build_cell_type_lookup(
+            &mut self,
+        ) -> (BlockUnitCellDictionaryType, Vec<CellTypeLookupError>) {
             let mut cell_type_lookup: BlockUnitCellDictionaryType = HashMap::new();
+            let mut errors: Vec<CellTypeLookupError> = Vec::new();
+            let tileset = match self.base().get_tileset() {
+                Some(tileset) => tileset,
+                None => return (cell_type_lookup, errors),
+            };
+
             // traverse the tileset and extract only the tiles that are of the type TileSetScenesCollectionSource
-            if self.base().get_tileset().is_some() {
-                let tileset = self.base().get_tileset().unwrap();
-                for source_index in 0..tileset.get_source_count() {
-                    let source_id = tileset.get_source_id(source_index);
-                    let possible_source: Option<Gd<TileSetSource>> = match tileset
-                        .get_source(source_id)
-                    {
-                        Some(tile_source) => {
-                            let is_scene_collection =
-                                tile_source.is_class("TileSetScenesCollectionSource".into());
-
-                            let tile_source_scenecollection: Option<
-                                Gd<TileSetScenesCollectionSource>,
-                            > = match is_scene_collection {
-                                true => {
-                                    let mut tile_source_as_scenecollection_mut =
-                                        tile_source.clone().cast::<TileSetScenesCollectionSource>();
-                                    let scenes_in_this_tile =
-                                        tile_source_as_scenecollection_mut.get_scene_tiles_count();
-                                    for scne_tiles_index in 0..scenes_in_this_tile {
-                                        let tile_id = tile_source_as_scenecollection_mut
-                                            .get_scene_tile_id(scne_tiles_index);
-                                        let possible_packed_scene: Option<Gd<PackedScene>> =
-                                            tile_source_as_scenecollection_mut
-                                                .get_scene_tile_scene(tile_id);
-                                        let possible_resource_path: Option<GString> =
-                                            match possible_packed_scene {
-                                                Some(packed_scene) => {
-                                                    // i.e. "res://scenes/block_units/voice.tscn"
-                                                    Some(packed_scene.get_path())
-                                                }
-                                                None => {
-                                                    godot_print!("tile_related::MyTileExtension::build_cell_type_lookup() - packed_scene is None");
-                                                    None
-                                                }
-                                            };
-
-                                        // We ASSUME that each scenes (.tscn) that are TileSet related, has an extra
-                                        // meta-data assigned, in which it has the BLockKeys enum value to it...
-
-                                        // check if this scene (possible_resource_path) already exists in the lookup dictionary
-                                        // if so, just update the source_id; else add it to the dictionary
-                                        //if let upserted_value =
-                                        //    cell_type_lookup.entry(tile_source.get_key())
-                                        //{
-                                        //    upserted_value.source_id = source_id;
-                                        //} else {
-                                        //    let block_unit_cell_kvp_value =
-                                        //        BlockUnitCellKVPValue {
-                                        //            source_id: source_id,
-                                        //            scene: possible_packed_scene,
-                                        //            resource_path: possible_resource_path,
-                                        //        };
-                                        //    cell_type_lookup.insert(
-                                        //        tile_source.get_key(),
-                                        //        block_unit_cell_kvp_value,
-                                        //    );
-                                        //}
-                                    }
-
-                                    Some(tile_source_as_scenecollection_mut)
-                                }
-                                false => {
-                                    godot_print!("tile_related::MyTileExtension::build_cell_type_lookup() - tile_source is not of class TileSetScenesCollectionSource");
-                                    None
-                                }
-                            };
-
-                            Some(tile_source)
+            for source_index in 0..tileset.get_source_count() {
+                let source_id = tileset.get_source_id(source_index);
+                let tile_source: Gd<TileSetSource> = match tileset.get_source(source_id) {
+                    Some(tile_source) => tile_source,
+                    None => {
+                        godot_print!("tile_related::MyTileExtension::build_cell_type_lookup() - tileset.get_source() returned None");
+                        continue;
+                    }
+                };
+                if !tile_source.is_class("TileSetScenesCollectionSource".into()) {
+                    godot_print!("tile_related::MyTileExtension::build_cell_type_lookup() - tile_source is not of class TileSetScenesCollectionSource");
+                    continue;
+                }
+                let scene_collection = tile_source.cast::<TileSetScenesCollectionSource>();
+                for scene_tiles_index in 0..scene_collection.get_scene_tiles_count() {
+                    let tile_id = scene_collection.get_scene_tile_id(scene_tiles_index);
+                    let packed_scene = match scene_collection.get_scene_tile_scene(tile_id) {
+                        Some(packed_scene) => packed_scene,
+                        None => {
+                            godot_print!("tile_related::MyTileExtension::build_cell_type_lookup() - packed_scene is None");
+                            continue;
                         }
+                    };
+                    // i.e. "res://scenes/block_units/void.tscn"
+                    let resource_path = packed_scene.get_path();
+
+                    let key = match Self::read_block_key_meta(&packed_scene) {
+                        Some(value) => match value.parse::<BlockKeys>() {
+                            Ok(key) => key,
+                            Err(_) => {
+                                errors.push(CellTypeLookupError::UnknownKey {
+                                    source_id,
+                                    resource_path,
+                                    value,
+                                });
+                                continue;
+                            }
+                        },
                         None => {
-                            godot_print!("tile_related::MyTileExtension::build_cell_type_lookup() - tileset.get_source() returned None");
-                            None
+                            errors.push(CellTypeLookupError::MissingKey {
+                                source_id,
+                                resource_path,
+                            });
+                            continue;
                         }
                     };
+
+                    if let Some(existing) = cell_type_lookup.get(&key) {
+                        errors.push(CellTypeLookupError::DuplicateKey {
+                            key,
+                            source_id,
+                            other_source_id: existing.source_id,
+                        });
+                        continue;
+                    }
+                    cell_type_lookup.insert(
+                        key,
+                        BlockUnitCellKVPValue {
+                            source_id,
+                            tile_id,
+                            scene: Some(packed_scene),
+                            resource_path: Some(resource_path),
+                        },
+                    );
                 }
             }
-            cell_type_lookup
+            (cell_type_lookup, errors)
+        }
+
+        // 'metadata/block_key' of the root node (index 0) without having to instantiate the scene
+        fn read_block_key_meta(packed_scene: &Gd<PackedScene>) -> Option<String> {
+            let state = packed_scene.get_state()?;
+            let root_node_index = 0;
+            for property_index in 0..state.get_node_property_count(root_node_index) {
+                let name = state.get_node_property_name(root_node_index, property_index);
+                if name == StringName::from("metadata/block_key") {
+                    let value = state.get_node_property_value(root_node_index, property_index);
+                    return value.try_to::<GString>().ok().map(|v| v.to_string());
+                }
+            }
+            None
+        }
+
+        // reverse lookup, BlockKeys::Undefined if the source_id is not a (known) block scene
+        fn key_from_source_id(&self, source_id: CellIdType) -> BlockKeys {
+            self.cell_type_lookup
+                .iter()
+                .find(|(_, value)| value.source_id == source_id)
+                .map(|(key, _)| *key)
+                .unwrap_or(BlockKeys::Undefined)
         }
     }
 }
@@ -0,0 +1,104 @@
+use crate::connectivity::Direction;
+use crate::flow::CellFlow;
+use crate::{BlockKeys, BlockUnitCell};
+
+// What the tile scene of a cell should be playing, worked out from the simulation (see
+// CellFlow) rather than by the scene itself, so that only the cells the stream is actually
+// going through animate, and in the direction it is going.
+// ForBlockUnits pushes these to the instanced scene tiles (see BlockUnitBase.cb_flow_state()).
+
+// NOTE: Same order as AnimationState in autoload_globals_tileanimation.gd (the i64 passed to
+// GDScript is the order of declaration)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AnimationState {
+    #[default]
+    None, // nothing to animate (no block on the cell)
+    Animate, // stream is going through the cell right now
+    Empty,   // stream never got here (yet)
+    Filled,  // stream went through and moved on
+}
+
+impl From<AnimationState> for i64 {
+    fn from(state: AnimationState) -> Self {
+        state as i64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CellAnimation {
+    pub state: AnimationState,
+    pub entry: Option<Direction>, // side the stream came in through (as placed on the grid)
+    // stream came in through another side than the one the block's animations start from
+    // (the first side, in N/E/S/W order, that takes the stream in the canonical orientation),
+    // i.e. a straight pipe filled from the South, so the animation has to run backwards
+    pub reversed: bool,
+}
+
+pub fn cell_animation(cell: &BlockUnitCell, flow: &CellFlow) -> CellAnimation {
+    if cell.key == BlockKeys::Void || cell.key == BlockKeys::Undefined {
+        return CellAnimation::default();
+    }
+    let state = match (flow.flowing, flow.volume > 0.0) {
+        (true, _) => AnimationState::Animate,
+        (false, true) => AnimationState::Filled,
+        (false, false) => AnimationState::Empty,
+    };
+    // back from the side on the grid to the side of the block in its canonical orientation
+    let canonical_entry = flow.entry.and_then(|entry| {
+        Direction::ALL
+            .into_iter()
+            .find(|side| cell.orientation.apply(*side) == entry)
+    });
+    let ports = cell.key.ports();
+    let first_entry = Direction::ALL
+        .into_iter()
+        .find(|side| matches!(ports.port(*side), Some(port) if port.accepts()));
+    CellAnimation {
+        state,
+        entry: flow.entry,
+        reversed: canonical_entry.is_some() && canonical_entry != first_entry,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orientation::Rotation;
+    use crate::test_utils::make_map;
+
+    #[test]
+    fn test_cell_animation() {
+        // a straight pipe turned to run East-West, its canonical North side is now East
+        let cells = make_map(2, 1, &[(0, 0, BlockKeys::Router1Straight, Rotation::Deg90)]);
+        let pipe = cells[0][0].unwrap();
+        assert_eq!(
+            cell_animation(&pipe, &CellFlow::default()).state,
+            AnimationState::Empty
+        );
+        assert_eq!(
+            cell_animation(&cells[1][0].unwrap(), &CellFlow::default()).state,
+            AnimationState::None
+        );
+
+        let mut flow = CellFlow {
+            volume: 1.0,
+            pressure: 1.0,
+            fill: 0.5,
+            flowing: true,
+            entry: Some(Direction::East),
+        };
+        assert_eq!(
+            cell_animation(&pipe, &flow),
+            CellAnimation {
+                state: AnimationState::Animate,
+                entry: Some(Direction::East),
+                reversed: false
+            }
+        );
+        flow.entry = Some(Direction::West);
+        flow.flowing = false;
+        let filled = cell_animation(&pipe, &flow);
+        assert_eq!(filled.state, AnimationState::Filled);
+        assert!(filled.reversed);
+    }
+}
@@ -0,0 +1,186 @@
+// Game clock for the Pipe-Mania mode: a countdown before the stream starts, then the stream
+// advances one cell every 'seconds_per_cell' (or every 'fast_seconds_per_cell' once the player
+// hits fast-forward), until the caller tells it the stream has ended.
+// The clock never looks at real time, it is only moved by advance(delta) (i.e. from
+// ForBlockUnits::process(delta)), so tests (and replays) can drive it with made-up deltas.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockSettings {
+    pub countdown_seconds: f32,
+    pub seconds_per_cell: f32,
+    pub fast_seconds_per_cell: f32, // while fast-forwarding
+}
+
+impl Default for ClockSettings {
+    fn default() -> Self {
+        ClockSettings {
+            countdown_seconds: 20.0,
+            seconds_per_cell: 2.0,
+            fast_seconds_per_cell: 0.1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockPhase {
+    Countdown,
+    Flowing,
+    Finished,
+}
+
+// What happened during one advance()
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ClockStep {
+    pub flow_started: bool, // countdown reached 0 during this step
+    pub flow_ticks: u32,    // how many times the stream should advance (can be >1 on a long frame)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameClock {
+    settings: ClockSettings,
+    phase: ClockPhase,
+    countdown_left: f32,
+    cell_elapsed: f32, // time spent on the current cell
+    fast_forward: bool,
+    paused: bool,
+    elapsed: f32, // total un-paused time
+}
+
+impl GameClock {
+    pub fn new(settings: ClockSettings) -> Self {
+        GameClock {
+            settings,
+            phase: ClockPhase::Countdown,
+            countdown_left: settings.countdown_seconds.max(0.0),
+            cell_elapsed: 0.0,
+            fast_forward: false,
+            paused: false,
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn settings(&self) -> &ClockSettings {
+        &self.settings
+    }
+
+    pub fn phase(&self) -> ClockPhase {
+        self.phase
+    }
+
+    pub fn countdown_left(&self) -> f32 {
+        self.countdown_left
+    }
+
+    pub fn elapsed(&self) -> f32 {
+        self.elapsed
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn is_fast_forward(&self) -> bool {
+        self.fast_forward
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    // Pipe Mania's "I'm done, let it flow": skips what is left of the countdown and speeds
+    // up the stream until the end (cannot be undone)
+    pub fn fast_forward(&mut self) {
+        self.fast_forward = true;
+        self.countdown_left = 0.0;
+    }
+
+    // the stream ended, no more ticks from here on
+    pub fn finish(&mut self) {
+        self.phase = ClockPhase::Finished;
+    }
+
+    fn seconds_per_cell(&self) -> f32 {
+        let seconds = match self.fast_forward {
+            true => self.settings.fast_seconds_per_cell,
+            false => self.settings.seconds_per_cell,
+        };
+        // guard against a zero (or negative) setting spinning forever
+        seconds.max(0.001)
+    }
+
+    pub fn advance(&mut self, delta_seconds: f32) -> ClockStep {
+        let mut step = ClockStep::default();
+        if self.paused || self.phase == ClockPhase::Finished || delta_seconds <= 0.0 {
+            return step;
+        }
+        self.elapsed += delta_seconds;
+        let mut delta = delta_seconds;
+
+        if self.phase == ClockPhase::Countdown {
+            if self.countdown_left > delta {
+                self.countdown_left -= delta;
+                return step;
+            }
+            // whatever is left of this frame goes towards the first cell
+            delta -= self.countdown_left;
+            self.countdown_left = 0.0;
+            self.phase = ClockPhase::Flowing;
+            step.flow_started = true;
+        }
+
+        self.cell_elapsed += delta;
+        let seconds_per_cell = self.seconds_per_cell();
+        while self.cell_elapsed >= seconds_per_cell {
+            self.cell_elapsed -= seconds_per_cell;
+            step.flow_ticks += 1;
+        }
+        step
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> ClockSettings {
+        ClockSettings {
+            countdown_seconds: 3.0,
+            seconds_per_cell: 1.0,
+            fast_seconds_per_cell: 0.25,
+        }
+    }
+
+    #[test]
+    fn test_countdown_then_flow() {
+        let mut clock = GameClock::new(settings());
+        assert_eq!(clock.advance(2.0), ClockStep::default());
+        assert_eq!(clock.phase(), ClockPhase::Countdown);
+        // 1.0 left of the countdown, 0.5 towards the first cell
+        let step = clock.advance(1.5);
+        assert!(step.flow_started);
+        assert_eq!(step.flow_ticks, 0);
+        assert_eq!(clock.advance(0.5).flow_ticks, 1);
+        // long frame, several cells at once
+        assert_eq!(clock.advance(3.0).flow_ticks, 3);
+        clock.finish();
+        assert_eq!(clock.advance(10.0), ClockStep::default());
+    }
+
+    #[test]
+    fn test_pause_and_fast_forward() {
+        let mut clock = GameClock::new(settings());
+        clock.pause();
+        assert_eq!(clock.advance(100.0), ClockStep::default());
+        assert_eq!(clock.countdown_left(), 3.0);
+        clock.resume();
+        clock.fast_forward();
+        let step = clock.advance(1.0);
+        assert!(step.flow_started);
+        assert_eq!(step.flow_ticks, 4);
+        assert_eq!(clock.elapsed(), 1.0);
+    }
+}
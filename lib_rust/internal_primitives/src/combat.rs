@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+
+use crate::units::{GobletId, GobletSimulator, GobletState};
+use crate::GridPosition;
+
+// Static towers for the second (custom) game mode, which shoot at the goblets passing by,
+// while bots (goblets with a CounterAttack) shoot back.
+// Towers occupy a cell (BlockKeys::Tower, which is an obstacle), and the combat step is meant
+// to be run right after GobletSimulator::tick() on every tick:
+//   let moves = goblets.tick(&cells);
+//   let report = combat.tick(&mut goblets);
+// NOTE: To keep it deterministic, everything is resolved in a fixed order: first all towers
+// fire (in tower id order), then all surviving bots fire back (in goblet id order), so a bot
+// that got killed this tick does not get a last shot off.
+
+pub type TowerId = u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TowerStats {
+    pub range: i32, // in cells (euclidean distance)
+    pub damage: i32,
+    pub fire_interval_ticks: u32, // fires at most once every N ticks
+    pub max_hp: i32,
+}
+
+impl Default for TowerStats {
+    fn default() -> Self {
+        TowerStats {
+            range: 2,
+            damage: 4,
+            fire_interval_ticks: 3,
+            max_hp: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tower {
+    pub id: TowerId,
+    pub position: GridPosition,
+    pub stats: TowerStats,
+    pub hp: i32,
+    cooldown: u32, // ticks until it can fire again
+}
+
+impl Tower {
+    pub fn is_destroyed(&self) -> bool {
+        self.hp <= 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CombatEvent {
+    TowerFired {
+        tower: TowerId,
+        target: GobletId,
+        damage: i32,
+    },
+    BotFired {
+        bot: GobletId,
+        target: TowerId,
+        damage: i32,
+    },
+    GobletKilled {
+        id: GobletId,
+        by: TowerId,
+    },
+    TowerDestroyed {
+        tower: TowerId,
+        by: GobletId,
+    },
+}
+
+// What happened in one combat step; 'kills' are towers taken down by bots, 'losses' are
+// goblets taken down by towers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CombatReport {
+    pub events: Vec<CombatEvent>,
+    pub kills: Vec<TowerId>,
+    pub losses: Vec<GobletId>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CombatSimulator {
+    towers: Vec<Tower>,
+    bot_cooldowns: HashMap<GobletId, u32>,
+}
+
+fn in_range(a: GridPosition, b: GridPosition, range: i32) -> bool {
+    let (dx, dy) = (a.x - b.x, a.y - b.y);
+    dx * dx + dy * dy <= range * range
+}
+
+fn distance_squared(a: GridPosition, b: GridPosition) -> i32 {
+    let (dx, dy) = (a.x - b.x, a.y - b.y);
+    dx * dx + dy * dy
+}
+
+impl CombatSimulator {
+    pub fn new() -> Self {
+        CombatSimulator::default()
+    }
+
+    // i.e. from Level::towers, all with default stats
+    pub fn from_positions(positions: &[GridPosition]) -> Self {
+        let mut combat = CombatSimulator::new();
+        for position in positions {
+            combat.add_tower(*position, TowerStats::default());
+        }
+        combat
+    }
+
+    pub fn add_tower(&mut self, position: GridPosition, stats: TowerStats) -> TowerId {
+        let id = self.towers.len() as TowerId;
+        self.towers.push(Tower {
+            id,
+            position,
+            stats,
+            hp: stats.max_hp,
+            cooldown: 0,
+        });
+        id
+    }
+
+    pub fn towers(&self) -> &[Tower] {
+        &self.towers
+    }
+
+    pub fn tower(&self, id: TowerId) -> Option<&Tower> {
+        self.towers.get(id as usize)
+    }
+
+    pub fn standing_count(&self) -> usize {
+        self.towers.iter().filter(|tower| !tower.is_destroyed()).count()
+    }
+
+    pub fn tick(&mut self, goblets: &mut GobletSimulator) -> CombatReport {
+        let mut report = CombatReport::default();
+
+        // towers fire at the closest goblet in range (lowest id on ties)
+        for tower in self.towers.iter_mut() {
+            if tower.is_destroyed() {
+                continue;
+            }
+            if tower.cooldown > 0 {
+                tower.cooldown -= 1;
+                continue;
+            }
+            let target = goblets
+                .goblets()
+                .iter()
+                .filter(|goblet| goblet.is_active())
+                .filter(|goblet| in_range(tower.position, goblet.position, tower.stats.range))
+                .min_by_key(|goblet| (distance_squared(tower.position, goblet.position), goblet.id))
+                .map(|goblet| goblet.id);
+            let target = match target.and_then(|id| goblets.goblet_mut(id)) {
+                Some(goblet) => goblet,
+                None => continue,
+            };
+            tower.cooldown = tower.stats.fire_interval_ticks.saturating_sub(1);
+            target.hp -= tower.stats.damage;
+            report.events.push(CombatEvent::TowerFired {
+                tower: tower.id,
+                target: target.id,
+                damage: tower.stats.damage,
+            });
+            if target.hp <= 0 {
+                target.state = GobletState::Killed;
+                report.events.push(CombatEvent::GobletKilled {
+                    id: target.id,
+                    by: tower.id,
+                });
+                report.losses.push(target.id);
+            }
+        }
+
+        // surviving bots fire back at the closest standing tower in range (lowest id on ties)
+        for goblet in goblets.goblets().iter().filter(|goblet| goblet.is_active()) {
+            let counter_attack = match goblet.stats.counter_attack {
+                Some(counter_attack) => counter_attack,
+                None => continue,
+            };
+            let cooldown = self.bot_cooldowns.entry(goblet.id).or_insert(0);
+            if *cooldown > 0 {
+                *cooldown -= 1;
+                continue;
+            }
+            let target = self
+                .towers
+                .iter_mut()
+                .filter(|tower| !tower.is_destroyed())
+                .filter(|tower| in_range(goblet.position, tower.position, counter_attack.range))
+                .min_by_key(|tower| (distance_squared(goblet.position, tower.position), tower.id));
+            let target = match target {
+                Some(tower) => tower,
+                None => continue,
+            };
+            *cooldown = counter_attack.fire_interval_ticks.saturating_sub(1);
+            target.hp -= counter_attack.damage;
+            report.events.push(CombatEvent::BotFired {
+                bot: goblet.id,
+                target: target.id,
+                damage: counter_attack.damage,
+            });
+            if target.is_destroyed() {
+                report.events.push(CombatEvent::TowerDestroyed {
+                    tower: target.id,
+                    by: goblet.id,
+                });
+                report.kills.push(target.id);
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connectivity::Direction;
+    use crate::units::GobletKind;
+
+    #[test]
+    fn test_tower_kills_in_range_only() {
+        let mut goblets = GobletSimulator::new(vec![]);
+        let near = goblets.spawn(GobletKind::Runner, GridPosition::new(1, 0), Direction::East);
+        let far = goblets.spawn(GobletKind::Runner, GridPosition::new(9, 9), Direction::East);
+        let mut combat = CombatSimulator::new();
+        combat.add_tower(
+            GridPosition::new(0, 0),
+            TowerStats {
+                damage: 5,
+                ..TowerStats::default()
+            },
+        );
+        let report = combat.tick(&mut goblets);
+        assert_eq!(report.losses, vec![near]);
+        assert_eq!(goblets.goblet(near).unwrap().state, GobletState::Killed);
+        assert!(goblets.goblet(far).unwrap().is_active());
+        // dead goblets are not shot at again, and the far one is out of range
+        for _ in 0..5 {
+            assert!(combat.tick(&mut goblets).losses.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_bot_fires_back() {
+        let mut goblets = GobletSimulator::new(vec![]);
+        let bot = goblets.spawn(GobletKind::Bot, GridPosition::new(1, 0), Direction::East);
+        let mut combat = CombatSimulator::from_positions(&[GridPosition::new(0, 0)]);
+        let bot_stats = GobletKind::Bot.stats();
+        let counter_attack = bot_stats.counter_attack.unwrap();
+        let tower_stats = TowerStats::default();
+
+        let report = combat.tick(&mut goblets);
+        assert!(report.events.contains(&CombatEvent::BotFired {
+            bot,
+            target: 0,
+            damage: counter_attack.damage
+        }));
+        assert_eq!(goblets.goblet(bot).unwrap().hp, bot_stats.max_hp - tower_stats.damage);
+        assert_eq!(combat.tower(0).unwrap().hp, tower_stats.max_hp - counter_attack.damage);
+
+        // bot (15 hp, 5 dmg every 2 ticks) outlasts the default tower (20 hp, 4 dmg every 3 ticks)
+        let mut kills = Vec::new();
+        for _ in 0..10 {
+            kills.extend(combat.tick(&mut goblets).kills);
+        }
+        assert_eq!(kills, vec![0]);
+        assert_eq!(combat.standing_count(), 0);
+        assert!(goblets.goblet(bot).unwrap().is_active());
+    }
+}
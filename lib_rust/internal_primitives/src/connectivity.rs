@@ -0,0 +1,350 @@
+use crate::topology::GridTopology;
+use crate::{BlockKeys, GridPosition};
+
+// Engine-independent model of which edges (sides) of a tile are open, and in which
+// direction the "stream" is allowed to pass through each of those openings.
+// Nothing in here should ever need Godot to be running, so that both router-game and
+// tdcraft-godot can reason about paths (i.e. in unit-tests, solvers, simulators).
+//
+// NOTE: All shapes are described in their canonical (unrotated) orientation, which
+// (by convention) has the "stem" of the asymmetric pieces pointing SOUTH:
+// * Router1Straight - North <-> South
+// * Router1Corner   - North <-> East
+// * Router1Cross    - North <-> South AND East <-> West (two separate channels, no turning)
+// * Router1Tee      - In: South; Out: East, West
+// * Router          - In: South; Out: North, East, West
+// * RouteJoin2To1   - In: East, West; Out: South
+// * RouteJoin3To1   - In: North, East, West; Out: South
+// * LineBlock1Edge  - blocks North; East, South, West open both ways
+// * LineBlock2Corner - blocks North and East; South, West open both ways
+// * LineBlock3T     - blocks North, East and West; only South open (a dead end)
+// * LineBlock4All   - blocks all sides
+// * Tower, Void and Undefined have no openings at all (obstacles/empty space)
+// LineBlock* are obstacles in that nothing can be placed on them (see placement.rs), but the
+// stream goes through the sides they leave open, and out through all the other open ones.
+
+// Sides of a (square) tile, in clockwise order starting from the top.
+// NOTE: Godot TileMap coordinates grows downward on the Y axis, hence North is (0, -1)
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum Direction {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::North,
+        Direction::East,
+        Direction::South,
+        Direction::West,
+    ];
+
+    pub fn opposite(self) -> Direction {
+        match self {
+            Direction::North => Direction::South,
+            Direction::East => Direction::West,
+            Direction::South => Direction::North,
+            Direction::West => Direction::East,
+        }
+    }
+
+    // (dx, dy) to get to the neighbour on this side on the square grid, anything walking the
+    // grid should go through GridTopology::port_neighbour() instead
+    pub fn offset(self) -> (i32, i32) {
+        match self {
+            Direction::North => (0, -1),
+            Direction::East => (1, 0),
+            Direction::South => (0, 1),
+            Direction::West => (-1, 0),
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::North => "North",
+            Direction::East => "East",
+            Direction::South => "South",
+            Direction::West => "West",
+        }
+    }
+}
+
+impl std::str::FromStr for Direction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Direction::ALL
+            .into_iter()
+            .find(|dir| dir.as_str() == s)
+            .ok_or(())
+    }
+}
+
+// Direction of the stream through a single opening
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum PortFlow {
+    In,    // stream can only enter through this side
+    Out,   // stream can only leave through this side
+    InOut, // either way (i.e. plain pipes)
+}
+
+impl PortFlow {
+    pub fn accepts(self) -> bool {
+        matches!(self, PortFlow::In | PortFlow::InOut)
+    }
+    pub fn emits(self) -> bool {
+        matches!(self, PortFlow::Out | PortFlow::InOut)
+    }
+}
+
+// The four openings of a tile (None means the side is closed)
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Default)]
+pub struct BlockPorts {
+    ports: [Option<PortFlow>; 4], // indexed by Direction (N, E, S, W)
+    crossing: bool, // true if the channels go straight through without turning (i.e. Router1Cross)
+}
+
+impl BlockPorts {
+    pub fn closed() -> Self {
+        BlockPorts::default()
+    }
+
+    pub fn with(mut self, side: Direction, flow: PortFlow) -> Self {
+        self.ports[side.index()] = Some(flow);
+        self
+    }
+
+    pub fn with_crossing(mut self) -> Self {
+        self.crossing = true;
+        self
+    }
+
+    pub fn port(&self, side: Direction) -> Option<PortFlow> {
+        self.ports[side.index()]
+    }
+
+    pub fn is_open(&self, side: Direction) -> bool {
+        self.port(side).is_some()
+    }
+
+    pub fn is_crossing(&self) -> bool {
+        self.crossing
+    }
+
+    pub fn open_sides(&self) -> Vec<Direction> {
+        Direction::ALL
+            .into_iter()
+            .filter(|side| self.is_open(*side))
+            .collect()
+    }
+
+    // If the stream enters via 'entry' side, which sides will it leave from?
+    // Returns empty if the stream cannot enter from that side at all.
+    pub fn exits_for(&self, entry: Direction) -> Vec<Direction> {
+        match self.port(entry) {
+            Some(flow) if flow.accepts() => {}
+            _ => return Vec::new(),
+        }
+        if self.crossing {
+            // crossing channels never turn, so it's either straight through or nothing
+            let straight = entry.opposite();
+            return match self.port(straight) {
+                Some(flow) if flow.emits() => vec![straight],
+                _ => Vec::new(),
+            };
+        }
+        Direction::ALL
+            .into_iter()
+            .filter(|side| *side != entry)
+            .filter(|side| matches!(self.port(*side), Some(flow) if flow.emits()))
+            .collect()
+    }
+}
+
+impl BlockKeys {
+    // Openings of this block in its canonical orientation (see notes at top of this file)
+    pub fn ports(&self) -> BlockPorts {
+        use Direction::*;
+        use PortFlow::*;
+        match self {
+            BlockKeys::Undefined
+            | BlockKeys::Void
+            | BlockKeys::LineBlock4All
+            | BlockKeys::Tower => BlockPorts::closed(),
+            BlockKeys::LineBlock1Edge => BlockPorts::closed()
+                .with(East, InOut)
+                .with(South, InOut)
+                .with(West, InOut),
+            BlockKeys::LineBlock2Corner => BlockPorts::closed().with(South, InOut).with(West, InOut),
+            BlockKeys::LineBlock3T => BlockPorts::closed().with(South, InOut),
+            BlockKeys::Router1Cross => BlockPorts::closed()
+                .with(North, InOut)
+                .with(East, InOut)
+                .with(South, InOut)
+                .with(West, InOut)
+                .with_crossing(),
+            BlockKeys::Router1Straight => BlockPorts::closed().with(North, InOut).with(South, InOut),
+            BlockKeys::Router1Corner => BlockPorts::closed().with(North, InOut).with(East, InOut),
+            BlockKeys::Router1Tee => BlockPorts::closed()
+                .with(South, In)
+                .with(East, Out)
+                .with(West, Out),
+            BlockKeys::Router => BlockPorts::closed()
+                .with(South, In)
+                .with(North, Out)
+                .with(East, Out)
+                .with(West, Out),
+            BlockKeys::RouteJoin2To1 => BlockPorts::closed()
+                .with(East, In)
+                .with(West, In)
+                .with(South, Out),
+            BlockKeys::RouteJoin3To1 => BlockPorts::closed()
+                .with(North, In)
+                .with(East, In)
+                .with(West, In)
+                .with(South, Out),
+        }
+    }
+
+    pub fn is_obstacle(&self) -> bool {
+        matches!(
+            self,
+            BlockKeys::LineBlock1Edge
+                | BlockKeys::LineBlock2Corner
+                | BlockKeys::LineBlock3T
+                | BlockKeys::LineBlock4All
+                | BlockKeys::Tower
+        )
+    }
+}
+
+// Can the stream leave tile 'from' (at from_position) and enter tile 'to' (at to_position)?
+// Both tiles must be neighbours (on the given topology), 'from' must emit on the shared side,
+// and 'to' must accept on its (opposite) shared side.
+pub fn connects(
+    topology: GridTopology,
+    from: &BlockPorts,
+    from_position: GridPosition,
+    to: &BlockPorts,
+    to_position: GridPosition,
+) -> bool {
+    match topology.port_between(from_position, to_position) {
+        Some(side) => {
+            matches!(from.port(side), Some(flow) if flow.emits())
+                && matches!(to.port(side.opposite()), Some(flow) if flow.accepts())
+        }
+        None => false,
+    }
+}
+
+// Same as connects() but ignores direction of the stream (i.e. "are the two pipes joined at all?")
+pub fn is_linked(
+    topology: GridTopology,
+    a: &BlockPorts,
+    a_position: GridPosition,
+    b: &BlockPorts,
+    b_position: GridPosition,
+) -> bool {
+    connects(topology, a, a_position, b, b_position)
+        || connects(topology, b, b_position, a, a_position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topology::GridSide;
+
+    #[test]
+    fn test_straight_to_east_neighbour() {
+        let square = GridTopology::Square;
+        // a horizontal pipe is a cross here, since canonical straight is vertical
+        let a = BlockKeys::Router1Cross.ports();
+        let b = BlockKeys::Router1Cross.ports();
+        let pos_a = GridPosition::new(2, 3);
+        let pos_b = square.port_neighbour(pos_a, Direction::East);
+        assert!(connects(square, &a, pos_a, &b, pos_b));
+        assert!(connects(square, &b, pos_b, &a, pos_a));
+
+        // vertical straight has nothing on its east side
+        let straight = BlockKeys::Router1Straight.ports();
+        assert!(!connects(square, &straight, pos_a, &b, pos_b));
+        assert!(!is_linked(square, &straight, pos_a, &b, pos_b));
+    }
+
+    #[test]
+    fn test_not_neighbours() {
+        let square = GridTopology::Square;
+        let a = BlockKeys::Router1Cross.ports();
+        let origin = GridPosition::new(0, 0);
+        assert!(!connects(square, &a, origin, &a, GridPosition::new(2, 0)));
+        assert!(!connects(square, &a, origin, &a, GridPosition::new(1, 1)));
+    }
+
+    #[test]
+    fn test_hex_sides_without_ports() {
+        // up-left and down-right neighbours of a hexagon share a side without a port
+        let cross = BlockKeys::Router1Cross.ports();
+        for hex in [GridTopology::HexOffset, GridTopology::HexAxial] {
+            let position = GridPosition::new(2, 2);
+            for side in hex.sides().iter().copied() {
+                let neighbour = hex.neighbour(position, side);
+                let has_port = !matches!(side, GridSide::NorthWest | GridSide::SouthEast);
+                assert_eq!(connects(hex, &cross, position, &cross, neighbour), has_port);
+            }
+        }
+    }
+
+    #[test]
+    fn test_one_way_ports() {
+        let square = GridTopology::Square;
+        // Router1Tee emits to the west, RouteJoin2To1 accepts from the east
+        let tee = BlockKeys::Router1Tee.ports();
+        let join = BlockKeys::RouteJoin2To1.ports();
+        let pos_tee = GridPosition::new(1, 0);
+        let pos_join = GridPosition::new(0, 0);
+        assert!(connects(square, &tee, pos_tee, &join, pos_join));
+        // but not the other way around
+        assert!(!connects(square, &join, pos_join, &tee, pos_tee));
+        assert!(is_linked(square, &join, pos_join, &tee, pos_tee));
+    }
+
+    #[test]
+    fn test_exits() {
+        let cross = BlockKeys::Router1Cross.ports();
+        assert_eq!(cross.exits_for(Direction::West), vec![Direction::East]);
+        let corner = BlockKeys::Router1Corner.ports();
+        assert_eq!(corner.exits_for(Direction::East), vec![Direction::North]);
+        assert!(corner.exits_for(Direction::South).is_empty());
+        let router = BlockKeys::Router.ports();
+        assert_eq!(
+            router.exits_for(Direction::South),
+            vec![Direction::North, Direction::East, Direction::West]
+        );
+        // cannot enter through an "out" only port
+        assert!(router.exits_for(Direction::North).is_empty());
+        assert!(BlockKeys::LineBlock4All.ports().open_sides().is_empty());
+    }
+
+    #[test]
+    fn test_line_blocks_only_block_their_sides() {
+        let edge = BlockKeys::LineBlock1Edge.ports();
+        assert!(edge.exits_for(Direction::North).is_empty());
+        assert_eq!(
+            edge.exits_for(Direction::West),
+            vec![Direction::East, Direction::South]
+        );
+        let corner = BlockKeys::LineBlock2Corner.ports();
+        assert_eq!(corner.exits_for(Direction::South), vec![Direction::West]);
+        assert!(corner.exits_for(Direction::East).is_empty());
+        // the stream can get into the T, but not out again
+        let tee = BlockKeys::LineBlock3T.ports();
+        assert_eq!(tee.open_sides(), vec![Direction::South]);
+        assert!(tee.exits_for(Direction::South).is_empty());
+    }
+}
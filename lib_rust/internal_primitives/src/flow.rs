@@ -0,0 +1,822 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::connectivity::{BlockPorts, Direction, PortFlow};
+use crate::topology::GridTopology;
+use crate::{cell_at, BlockKeys, CellMap, GridPosition};
+
+// Headless, deterministic Pipe-Mania-like flow ("goo") simulation over the cell_map grid.
+// Each call to tick() advances every active stream by exactly ONE cell; how often tick()
+// gets called (i.e. once the pre-flow countdown reaches 0, and then every N seconds) is
+// up to the caller, so that this can be unit-tested without the engine.
+// The grid is passed in on each tick() rather than owned, because the player can (and will)
+// keep placing tiles ahead of the stream while it is flowing.
+// Every start (source) begins its own stream of SOURCE_VOLUME, all of them on the same tick:
+// * a split (Router1Tee, Router) either duplicates the stream down every way out, or divides
+//   its volume between them (see SplitMode, per level)
+// * a join (RouteJoin2To1, RouteJoin3To1) lets the first stream through; streams arriving
+//   later through its other in-sides merge into it, adding their volume to whatever came out
+//   of the join (the streams still flowing, or the goals they already made it into)
+// * goals collect the volume of every stream that makes it in, and a goal can require a
+//   minimum volume (see with_goal_fills()), so a level can have the player feed two goals
+//   at once, or feed one goal from two starts
+// Which cell is next to which goes by the GridTopology (square by default, see topology.rs).
+// What the streams left in each cell is kept as a CellFlow (volume, pressure, fill level),
+// updated on every tick, for the tile scenes to animate from.
+
+pub const SOURCE_VOLUME: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlowEvent {
+    // stream left 'position' through 'towards' side (always followed by the matching
+    // Entered, Merged or ReachedGoal of the neighbour on that side)
+    Exited {
+        position: GridPosition,
+        towards: Direction,
+    },
+    // stream entered the cell through 'from' side
+    Entered {
+        position: GridPosition,
+        from: Direction,
+    },
+    // stream tried to leave 'position' towards 'towards' side but there was nothing
+    // on the other side (off the grid, a hole, or a Void cell)
+    Spilled {
+        position: GridPosition,
+        towards: Direction,
+    },
+    // stream is stuck at 'position': the neighbour on 'towards' side is a tile that cannot
+    // accept it (obstacle, wrong orientation, one-way port, or an already filled pipe);
+    // 'towards' is None if the cell itself has no way out
+    DeadEnd {
+        position: GridPosition,
+        towards: Option<Direction>,
+    },
+    // stream made it into one of the goal cells (through 'from' side), the stream ends there
+    ReachedGoal {
+        position: GridPosition,
+        from: Direction,
+    },
+    // stream ran into a join (through 'from' side) that another stream already went through,
+    // and merged into it; the stream ends there, its volume goes on with the other one
+    Merged {
+        position: GridPosition,
+        from: Direction,
+    },
+    // goal now holds at least the volume it requires (only for goals that require one)
+    GoalFilled {
+        position: GridPosition,
+        volume: f32,
+    },
+}
+
+// What a split does with the volume of the stream coming in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SplitMode {
+    #[default]
+    Duplicate, // every way out carries the full volume
+    Divide,    // the volume is shared equally between the ways out
+}
+
+impl SplitMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SplitMode::Duplicate => "duplicate",
+            SplitMode::Divide => "divide",
+        }
+    }
+
+    // volume each of 'count' ways out (or heirs of a merge) gets
+    fn share(&self, volume: f32, count: usize) -> f32 {
+        match self {
+            SplitMode::Duplicate => volume,
+            SplitMode::Divide => volume / count as f32,
+        }
+    }
+}
+
+impl std::str::FromStr for SplitMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [SplitMode::Duplicate, SplitMode::Divide]
+            .into_iter()
+            .find(|mode| mode.as_str() == s)
+            .ok_or(())
+    }
+}
+
+// Per cell state of the simulation (all 0 for cells the stream never reached)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CellFlow {
+    pub volume: f32,   // total that went in (every stream, both channels of a crossing, merges)
+    pub pressure: f32, // volume of the strongest stream that went through, per SOURCE_VOLUME
+    // 0.0 to 1.0: half of its final level on the tick the stream enters (the goo is on its
+    // way across), and the final level once the stream moved on, which is less than full if
+    // the stream was a divided one (see SplitMode)
+    pub fill: f32,
+    pub flowing: bool,            // stream entered on the last tick and is still in there
+    pub entry: Option<Direction>, // side the (first) stream came in through
+}
+
+impl CellFlow {
+    fn final_fill(&self) -> f32 {
+        (self.volume / SOURCE_VOLUME).min(1.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowState {
+    Running,
+    Ended, // no more active streams (all spilled and/or dead-ended)
+}
+
+// The "front" of a stream, it is about to leave 'position' through 'exit' side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlowHead {
+    pub position: GridPosition,
+    pub exit: Direction,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Stream {
+    head: FlowHead,
+    volume: f32,
+    joins: Vec<GridPosition>, // joins the stream went through, so merges can find it
+}
+
+// A goal, and what made it in so far
+#[derive(Debug, Clone, PartialEq)]
+struct GoalState {
+    position: GridPosition,
+    required: Option<f32>,
+    volume: f32,
+    // joins each of the streams that made it in went through
+    arrivals: Vec<Vec<GridPosition>>,
+}
+
+// other one-way in-sides than 'entry' make the cell a join
+fn is_join(ports: &BlockPorts, entry: Direction) -> bool {
+    Direction::ALL
+        .into_iter()
+        .any(|side| side != entry && ports.port(side) == Some(PortFlow::In))
+}
+
+#[derive(Debug, Clone)]
+pub struct FlowSimulator {
+    streams: Vec<Stream>,
+    split_mode: SplitMode,
+    topology: GridTopology,
+    // cell channels already filled; a crossing has two channels (vertical and horizontal)
+    // so that it can be filled twice, everything else is a single channel
+    filled_channels: HashSet<(GridPosition, Option<bool>)>,
+    // sides of the joins streams came in through
+    join_entries: HashSet<(GridPosition, Direction)>,
+    filled_order: Vec<GridPosition>, // in the order the stream reached them (may repeat for crossings)
+    cell_flows: HashMap<GridPosition, CellFlow>,
+    // goals are (like the source) buildings rather than tiles, so they accept from any side
+    goals: Vec<GoalState>,
+    reached_goals: Vec<GridPosition>,
+    tick_count: u64,
+    state: FlowState,
+}
+
+impl FlowSimulator {
+    // 'source' is the starting cell, and the stream leaves it through 'exit' on the first tick
+    pub fn new(source: GridPosition, exit: Direction) -> Self {
+        FlowSimulator::from_sources(vec![FlowHead {
+            position: source,
+            exit,
+        }])
+    }
+
+    // one stream per source, all of them start on the first tick
+    pub fn from_sources(sources: Vec<FlowHead>) -> Self {
+        FlowSimulator {
+            streams: sources
+                .into_iter()
+                .map(|head| Stream {
+                    head,
+                    volume: SOURCE_VOLUME,
+                    joins: Vec::new(),
+                })
+                .collect(),
+            split_mode: SplitMode::default(),
+            topology: GridTopology::default(),
+            filled_channels: HashSet::new(),
+            join_entries: HashSet::new(),
+            filled_order: Vec::new(),
+            cell_flows: HashMap::new(),
+            goals: Vec::new(),
+            reached_goals: Vec::new(),
+            tick_count: 0,
+            state: FlowState::Running,
+        }
+    }
+
+    pub fn with_goals(mut self, goals: Vec<GridPosition>) -> Self {
+        self.goals = goals
+            .into_iter()
+            .map(|position| GoalState {
+                position,
+                required: None,
+                volume: 0.0,
+                arrivals: Vec::new(),
+            })
+            .collect();
+        self
+    }
+
+    // minimum volume for (some of) the goals set by with_goals()
+    pub fn with_goal_fills(mut self, fills: &[(GridPosition, f32)]) -> Self {
+        for goal in self.goals.iter_mut() {
+            goal.required = fills
+                .iter()
+                .find(|(position, _)| *position == goal.position)
+                .map(|(_, volume)| *volume);
+        }
+        self
+    }
+
+    pub fn with_split_mode(mut self, split_mode: SplitMode) -> Self {
+        self.split_mode = split_mode;
+        self
+    }
+
+    pub fn with_topology(mut self, topology: GridTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn topology(&self) -> GridTopology {
+        self.topology
+    }
+
+    pub fn goals(&self) -> Vec<GridPosition> {
+        self.goals.iter().map(|goal| goal.position).collect()
+    }
+
+    // goals reached so far, in the order the stream got to them
+    pub fn reached_goals(&self) -> &[GridPosition] {
+        &self.reached_goals
+    }
+
+    // volume that made it into the goal so far (0 if 'position' is not a goal)
+    pub fn goal_volume(&self, position: GridPosition) -> f32 {
+        self.goal(position).map(|goal| goal.volume).unwrap_or(0.0)
+    }
+
+    // true if the goal holds the volume it requires (or does not require any)
+    pub fn is_goal_filled(&self, position: GridPosition) -> bool {
+        self.goal(position)
+            .map(|goal| goal.volume >= goal.required.unwrap_or(0.0))
+            .unwrap_or(false)
+    }
+
+    // goals that require a volume but do not hold it (yet)
+    pub fn unfilled_goals(&self) -> Vec<GridPosition> {
+        self.goals
+            .iter()
+            .filter(|goal| goal.required.is_some() && !self.is_goal_filled(goal.position))
+            .map(|goal| goal.position)
+            .collect()
+    }
+
+    pub fn state(&self) -> FlowState {
+        self.state
+    }
+
+    pub fn tick_count(&self) -> u64 {
+        self.tick_count
+    }
+
+    // number of cells filled so far (a crossing filled twice counts twice)
+    pub fn filled_length(&self) -> usize {
+        self.filled_order.len()
+    }
+
+    pub fn filled_cells(&self) -> &[GridPosition] {
+        &self.filled_order
+    }
+
+    pub fn is_reached(&self, position: GridPosition) -> bool {
+        self.filled_order.contains(&position)
+    }
+
+    // Cells the streams are about to leave (i.e. where the goo currently is)
+    pub fn head_positions(&self) -> Vec<GridPosition> {
+        self.streams.iter().map(|stream| stream.head.position).collect()
+    }
+
+    pub fn heads(&self) -> Vec<FlowHead> {
+        self.streams.iter().map(|stream| stream.head).collect()
+    }
+
+    // 'channel' is None for a regular cell, Some(true) for the vertical and Some(false) for the
+    // horizontal channel of a crossing (a crossing filled North-South can still be filled
+    // East-West)
+    pub fn is_channel_filled(&self, position: GridPosition, channel: Option<bool>) -> bool {
+        self.filled_channels.contains(&(position, channel))
+    }
+
+    // what the streams left in the cell so far
+    pub fn cell_flow(&self, position: GridPosition) -> CellFlow {
+        self.cell_flows.get(&position).copied().unwrap_or_default()
+    }
+
+    fn goal(&self, position: GridPosition) -> Option<&GoalState> {
+        self.goals.iter().find(|goal| goal.position == position)
+    }
+
+    // adds to the goal, and says so once it holds what it requires
+    fn fill_goal(&mut self, index: usize, volume: f32, events: &mut Vec<FlowEvent>) {
+        let goal = &mut self.goals[index];
+        let was_filled = goal.volume >= goal.required.unwrap_or(f32::INFINITY);
+        goal.volume += volume;
+        if let Some(required) = goal.required {
+            if !was_filled && goal.volume >= required {
+                events.push(FlowEvent::GoalFilled {
+                    position: goal.position,
+                    volume: goal.volume,
+                });
+            }
+        }
+    }
+
+    // Volume merged into 'join' goes on with the streams that came out of it; or, if they all
+    // ended already, into the goals they made it into (otherwise it is lost like they were)
+    fn merge(&mut self, join: GridPosition, volume: f32, events: &mut Vec<FlowEvent>) {
+        let heirs: Vec<usize> = (0..self.streams.len())
+            .filter(|index| self.streams[*index].joins.contains(&join))
+            .collect();
+        if !heirs.is_empty() {
+            let share = self.split_mode.share(volume, heirs.len());
+            for index in heirs {
+                self.streams[index].volume += share;
+            }
+            return;
+        }
+        let goals: Vec<(usize, usize)> = self
+            .goals
+            .iter()
+            .enumerate()
+            .map(|(index, goal)| {
+                let count = goal
+                    .arrivals
+                    .iter()
+                    .filter(|joins| joins.contains(&join))
+                    .count();
+                (index, count)
+            })
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        let total: usize = goals.iter().map(|(_, count)| count).sum();
+        for (index, count) in goals {
+            let share = self.split_mode.share(volume, total) * count as f32;
+            self.fill_goal(index, share, events);
+        }
+    }
+
+    fn settle_cells(&mut self) {
+        for cell_flow in self.cell_flows.values_mut().filter(|cell| cell.flowing) {
+            cell_flow.flowing = false;
+            cell_flow.fill = cell_flow.final_fill();
+        }
+    }
+
+    // stream of 'volume' came into the cell through 'entry'
+    fn pour(&mut self, position: GridPosition, entry: Direction, volume: f32) {
+        let cell_flow = self.cell_flows.entry(position).or_default();
+        cell_flow.volume += volume;
+        cell_flow.pressure = cell_flow.pressure.max(volume / SOURCE_VOLUME);
+        cell_flow.entry = cell_flow.entry.or(Some(entry));
+        cell_flow.flowing = true;
+        cell_flow.fill = cell_flow.final_fill() / 2.0;
+    }
+
+    pub fn tick(&mut self, cells: &CellMap) -> Vec<FlowEvent> {
+        let mut events = Vec::new();
+        if self.state == FlowState::Ended {
+            return events;
+        }
+        self.tick_count += 1;
+        // whatever was flowing on the last tick has moved on (or ended) by now
+        self.settle_cells();
+
+        let mut next_streams = Vec::new();
+        let mut merges = Vec::new();
+        for stream in std::mem::take(&mut self.streams) {
+            let head = stream.head;
+            let target = self.topology.port_neighbour(head.position, head.exit);
+            let entry = head.exit.opposite();
+            let dead_end = FlowEvent::DeadEnd {
+                position: head.position,
+                towards: Some(head.exit),
+            };
+
+            let exited = FlowEvent::Exited {
+                position: head.position,
+                towards: head.exit,
+            };
+
+            if let Some(index) = self.goals.iter().position(|goal| goal.position == target) {
+                events.push(exited);
+                events.push(FlowEvent::ReachedGoal {
+                    position: target,
+                    from: entry,
+                });
+                if !self.reached_goals.contains(&target) {
+                    self.reached_goals.push(target);
+                }
+                self.goals[index].arrivals.push(stream.joins);
+                self.fill_goal(index, stream.volume, &mut events);
+                continue;
+            }
+
+            let ports = match cell_at(cells, target) {
+                Some(cell) if cell.key != BlockKeys::Void && cell.key != BlockKeys::Undefined => {
+                    cell.ports()
+                }
+                _ => {
+                    // off the grid, a hole in the TileMap, or nothing placed yet
+                    events.push(FlowEvent::Spilled {
+                        position: head.position,
+                        towards: head.exit,
+                    });
+                    continue;
+                }
+            };
+            if !matches!(ports.port(entry), Some(flow) if flow.accepts()) {
+                events.push(dead_end);
+                continue;
+            }
+            let join = is_join(&ports, entry);
+            if join && !self.join_entries.insert((target, entry)) {
+                // something already came in through this very side
+                events.push(dead_end);
+                continue;
+            }
+            // an already filled channel blocks the stream just like a wall would (unless it
+            // is a join, which the stream merges into)
+            let channel = match ports.is_crossing() {
+                true => Some(matches!(entry, Direction::North | Direction::South)),
+                false => None,
+            };
+            if !self.filled_channels.insert((target, channel)) {
+                if join {
+                    events.push(exited);
+                    events.push(FlowEvent::Merged {
+                        position: target,
+                        from: entry,
+                    });
+                    merges.push((target, stream.volume));
+                    self.pour(target, entry, stream.volume);
+                } else {
+                    events.push(dead_end);
+                }
+                continue;
+            }
+
+            self.filled_order.push(target);
+            self.pour(target, entry, stream.volume);
+            events.push(exited);
+            events.push(FlowEvent::Entered {
+                position: target,
+                from: entry,
+            });
+            let exits = ports.exits_for(entry);
+            if exits.is_empty() {
+                events.push(FlowEvent::DeadEnd {
+                    position: target,
+                    towards: None,
+                });
+            }
+            let volume = self.split_mode.share(stream.volume, exits.len().max(1));
+            let mut joins = stream.joins;
+            if join {
+                joins.push(target);
+            }
+            for exit in exits {
+                next_streams.push(Stream {
+                    head: FlowHead {
+                        position: target,
+                        exit,
+                    },
+                    volume,
+                    joins: joins.clone(),
+                });
+            }
+        }
+
+        self.streams = next_streams;
+        // after every stream moved, so that merges on this tick find the streams that left
+        // the join on this tick as well
+        for (join, volume) in merges {
+            self.merge(join, volume, &mut events);
+        }
+        if self.streams.is_empty() {
+            self.state = FlowState::Ended;
+            // there is no next tick to do it
+            self.settle_cells();
+        }
+        events
+    }
+
+    // Convenience to run until the stream ends (bounded, in case caller passes a huge map)
+    pub fn run_to_end(&mut self, cells: &CellMap, max_ticks: u64) -> Vec<FlowEvent> {
+        let mut events = Vec::new();
+        for _ in 0..max_ticks {
+            if self.state == FlowState::Ended {
+                break;
+            }
+            events.extend(self.tick(cells));
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orientation::Rotation;
+    use crate::test_utils::make_map;
+
+    #[test]
+    fn test_straight_line_then_spill() {
+        // source at (0,0) flowing east into two horizontal pipes, then void
+        let cells = make_map(
+            4,
+            1,
+            &[
+                (1, 0, BlockKeys::Router1Straight, Rotation::Deg90),
+                (2, 0, BlockKeys::Router1Straight, Rotation::Deg90),
+            ],
+        );
+        let mut sim = FlowSimulator::new(GridPosition::new(0, 0), Direction::East);
+        let events = sim.tick(&cells);
+        assert_eq!(
+            events,
+            vec![
+                FlowEvent::Exited {
+                    position: GridPosition::new(0, 0),
+                    towards: Direction::East
+                },
+                FlowEvent::Entered {
+                    position: GridPosition::new(1, 0),
+                    from: Direction::West
+                }
+            ]
+        );
+        sim.tick(&cells);
+        let events = sim.tick(&cells);
+        assert_eq!(
+            events,
+            vec![FlowEvent::Spilled {
+                position: GridPosition::new(2, 0),
+                towards: Direction::East
+            }]
+        );
+        assert_eq!(sim.state(), FlowState::Ended);
+        assert_eq!(sim.filled_length(), 2);
+        // ended simulation does not advance
+        assert!(sim.tick(&cells).is_empty());
+        assert_eq!(sim.tick_count(), 3);
+    }
+
+    #[test]
+    fn test_loop_through_crossing() {
+        // a loop that crosses itself, entering the cross from the west the first time
+        // and from the north the second time:
+        //   (1,0) corner E-S   (2,0) corner S-W
+        //   (1,1) cross        (2,1) corner W-N
+        let cells = make_map(
+            3,
+            3,
+            &[
+                (1, 1, BlockKeys::Router1Cross, Rotation::Deg0),
+                (2, 1, BlockKeys::Router1Corner, Rotation::Deg270), // West <-> North
+                (2, 0, BlockKeys::Router1Corner, Rotation::Deg180), // South <-> West
+                (1, 0, BlockKeys::Router1Corner, Rotation::Deg90),  // East <-> South
+            ],
+        );
+        let mut sim = FlowSimulator::new(GridPosition::new(0, 1), Direction::East);
+        let events = sim.run_to_end(&cells, 100);
+        // cross, corner, corner, corner, cross again (vertical), then spill into the void below
+        assert_eq!(sim.filled_length(), 5);
+        assert_eq!(sim.filled_cells()[4], GridPosition::new(1, 1));
+        assert_eq!(
+            events.last(),
+            Some(&FlowEvent::Spilled {
+                position: GridPosition::new(1, 1),
+                towards: Direction::South
+            })
+        );
+    }
+
+    #[test]
+    fn test_dead_end_and_split() {
+        // Router1Tee accepts from South, so flowing north into it splits east and west
+        let cells = make_map(
+            3,
+            2,
+            &[
+                (1, 0, BlockKeys::Router1Tee, Rotation::Deg0),
+                (0, 0, BlockKeys::RouteJoin2To1, Rotation::Deg0), // in from East, out South
+            ],
+        );
+        let mut sim = FlowSimulator::new(GridPosition::new(1, 1), Direction::North);
+        sim.tick(&cells);
+        assert_eq!(sim.head_positions().len(), 2);
+        let events = sim.tick(&cells);
+        assert!(events.contains(&FlowEvent::Entered {
+            position: GridPosition::new(0, 0),
+            from: Direction::East
+        }));
+        assert!(events.contains(&FlowEvent::Spilled {
+            position: GridPosition::new(1, 0),
+            towards: Direction::East
+        }));
+        // join's out (south) side leads into a void
+        let events = sim.tick(&cells);
+        assert_eq!(
+            events,
+            vec![FlowEvent::Spilled {
+                position: GridPosition::new(0, 0),
+                towards: Direction::South
+            }]
+        );
+
+        // an obstacle cannot be entered at all
+        let cells = make_map(2, 1, &[(1, 0, BlockKeys::LineBlock4All, Rotation::Deg0)]);
+        let mut sim = FlowSimulator::new(GridPosition::new(0, 0), Direction::East);
+        assert_eq!(
+            sim.tick(&cells),
+            vec![FlowEvent::DeadEnd {
+                position: GridPosition::new(0, 0),
+                towards: Some(Direction::East)
+            }]
+        );
+    }
+
+    #[test]
+    fn test_reached_goal() {
+        // S = G
+        let cells = make_map(3, 1, &[(1, 0, BlockKeys::Router1Straight, Rotation::Deg90)]);
+        let goal = GridPosition::new(2, 0);
+        let mut sim =
+            FlowSimulator::new(GridPosition::new(0, 0), Direction::East).with_goals(vec![goal]);
+        let events = sim.run_to_end(&cells, 10);
+        assert_eq!(
+            events.last(),
+            Some(&FlowEvent::ReachedGoal {
+                position: goal,
+                from: Direction::West
+            })
+        );
+        assert_eq!(sim.reached_goals(), &[goal]);
+        assert_eq!(sim.state(), FlowState::Ended);
+        // the goal is not a tile, so it does not count towards the filled length
+        assert_eq!(sim.filled_length(), 1);
+    }
+
+    #[test]
+    fn test_two_starts_merge_into_one_goal() {
+        // A > J < = B     two starts feeding the join, B one cell further away
+        //     G           goal below the join needs both of them
+        let cells = make_map(
+            4,
+            2,
+            &[
+                (1, 0, BlockKeys::RouteJoin2To1, Rotation::Deg0), // in East/West, out South
+                (2, 0, BlockKeys::Router1Straight, Rotation::Deg90),
+            ],
+        );
+        let goal = GridPosition::new(1, 1);
+        let mut sim = FlowSimulator::from_sources(vec![
+            FlowHead {
+                position: GridPosition::new(0, 0),
+                exit: Direction::East,
+            },
+            FlowHead {
+                position: GridPosition::new(3, 0),
+                exit: Direction::West,
+            },
+        ])
+        .with_goals(vec![goal])
+        .with_goal_fills(&[(goal, 2.0)]);
+        sim.tick(&cells);
+        assert_eq!(sim.heads().len(), 2);
+        // A is already in the goal by the time B gets to the join, the goal still gets B
+        let events = sim.tick(&cells);
+        assert!(events.contains(&FlowEvent::ReachedGoal {
+            position: goal,
+            from: Direction::North
+        }));
+        assert!(events.contains(&FlowEvent::Merged {
+            position: GridPosition::new(1, 0),
+            from: Direction::East
+        }));
+        assert_eq!(
+            events.last(),
+            Some(&FlowEvent::GoalFilled {
+                position: goal,
+                volume: 2.0
+            })
+        );
+        assert!(sim.unfilled_goals().is_empty());
+        assert_eq!(sim.state(), FlowState::Ended);
+    }
+
+    #[test]
+    fn test_split_divides_volume() {
+        // G < T > G   Router1Tee fed from below, both goals need the full volume
+        //     S
+        let cells = make_map(3, 2, &[(1, 0, BlockKeys::Router1Tee, Rotation::Deg0)]);
+        let goals = vec![GridPosition::new(0, 0), GridPosition::new(2, 0)];
+        let fills = [(goals[0], SOURCE_VOLUME), (goals[1], SOURCE_VOLUME)];
+        let start = GridPosition::new(1, 1);
+
+        let mut sim = FlowSimulator::new(start, Direction::North)
+            .with_goals(goals.clone())
+            .with_goal_fills(&fills);
+        sim.run_to_end(&cells, 10);
+        assert!(sim.unfilled_goals().is_empty());
+
+        let mut sim = FlowSimulator::new(start, Direction::North)
+            .with_goals(goals.clone())
+            .with_goal_fills(&fills)
+            .with_split_mode(SplitMode::Divide);
+        sim.run_to_end(&cells, 10);
+        assert_eq!(sim.goal_volume(goals[0]), SOURCE_VOLUME / 2.0);
+        assert!(!sim.is_goal_filled(goals[1]));
+        assert_eq!(sim.unfilled_goals(), goals);
+    }
+
+    #[test]
+    fn test_cell_flow() {
+        // = < T > =   divided by the Router1Tee, each pipe next to it only gets half
+        //       S
+        let cells = make_map(
+            5,
+            2,
+            &[
+                (2, 0, BlockKeys::Router1Tee, Rotation::Deg0),
+                (1, 0, BlockKeys::Router1Straight, Rotation::Deg90),
+                (3, 0, BlockKeys::Router1Straight, Rotation::Deg90),
+            ],
+        );
+        let tee = GridPosition::new(2, 0);
+        let pipe = GridPosition::new(1, 0);
+        let mut sim = FlowSimulator::new(GridPosition::new(2, 1), Direction::North)
+            .with_split_mode(SplitMode::Divide);
+        sim.tick(&cells);
+        let flowing = sim.cell_flow(tee);
+        assert!(flowing.flowing);
+        assert_eq!(flowing.fill, 0.5);
+        assert_eq!(flowing.entry, Some(Direction::South));
+
+        sim.tick(&cells);
+        let filled = sim.cell_flow(tee);
+        assert!(!filled.flowing);
+        assert_eq!((filled.fill, filled.volume, filled.pressure), (1.0, 1.0, 1.0));
+        let half = sim.cell_flow(pipe);
+        assert!(half.flowing);
+        assert_eq!((half.fill, half.pressure), (0.25, 0.5));
+
+        sim.tick(&cells);
+        assert_eq!(sim.cell_flow(pipe).fill, 0.5);
+        assert_eq!(sim.cell_flow(GridPosition::new(0, 1)), CellFlow::default());
+    }
+
+    #[test]
+    fn test_iso_topology() {
+        // the isometric grid is only turned on screen, the stream goes through the same cells
+        let cells = make_map(3, 3, &[(1, 1, BlockKeys::Router1Straight, Rotation::Deg0)]);
+        let goal = GridPosition::new(1, 0);
+        let mut sim = FlowSimulator::new(GridPosition::new(1, 2), Direction::North)
+            .with_goals(vec![goal])
+            .with_topology(GridTopology::IsoDiamond);
+        sim.run_to_end(&cells, 10);
+        assert_eq!(sim.reached_goals(), &[goal]);
+        assert_eq!(sim.filled_cells(), &[GridPosition::new(1, 1)]);
+    }
+
+    #[test]
+    fn test_hex_topology() {
+        // on the hexagons, North is up-right; in offset coordinates that is straight up from
+        // an even row, and up and to the right from an odd one, in axial ones always up and
+        // to the right, so the same straight pipe leads somewhere else on each
+        let cells = make_map(4, 3, &[(1, 1, BlockKeys::Router1Straight, Rotation::Deg0)]);
+        for (topology, start, goal) in [
+            (GridTopology::HexOffset, (1, 2), (2, 0)),
+            (GridTopology::HexAxial, (0, 2), (2, 0)),
+        ] {
+            let goal = GridPosition::new(goal.0, goal.1);
+            let mut sim = FlowSimulator::new(GridPosition::new(start.0, start.1), Direction::North)
+                .with_goals(vec![goal])
+                .with_topology(topology);
+            sim.run_to_end(&cells, 10);
+            assert_eq!(sim.reached_goals(), &[goal], "{:?}", topology);
+            assert_eq!(sim.filled_cells(), &[GridPosition::new(1, 1)]);
+        }
+    }
+}
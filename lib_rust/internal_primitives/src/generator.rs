@@ -0,0 +1,355 @@
+use crate::connectivity::Direction;
+use crate::level::{Level, LevelCell, LevelStart, LEVEL_FORMAT_VERSION};
+use crate::orientation::{Orientation, OrientedBlock, Rotation};
+use crate::rng::SeededRng;
+use crate::scoring::ScoringRules;
+use crate::solver::{solve, SolveReport, SolverSettings};
+use crate::tile_queue::{TileQueue, TileQueueWeights};
+use crate::topology::GridTopology;
+use crate::{BlockKeys, GridPosition};
+
+// Procedural levels (i.e. endless mode, or a "daily" level off of the date as the seed):
+// given the grid size, a difficulty and a seed, lays down a start and a goal, LineBlock*
+// obstacles and a few pre-placed pieces, picks the queue, and then runs the solver (see
+// solver.rs) over it; levels the solver cannot win are thrown away and the next attempt is
+// made with the next seed off of the same rng, so the same settings always end up with the
+// same level.
+// Difficulty decides how far apart start and goal are, how cluttered the grid is, how much
+// time the player gets, and how friendly the queue is.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+        }
+    }
+
+    // percent of the grid covered with obstacles
+    fn obstacle_percent(&self) -> u64 {
+        match self {
+            Difficulty::Easy => 5,
+            Difficulty::Normal => 10,
+            Difficulty::Hard => 18,
+        }
+    }
+
+    // pre-placed pieces (and whether the player may replace them)
+    fn pre_placed(&self) -> (u64, bool) {
+        match self {
+            Difficulty::Easy => (1, false),
+            Difficulty::Normal => (2, false),
+            Difficulty::Hard => (3, true),
+        }
+    }
+
+    // (countdown, seconds per cell)
+    fn timing(&self) -> (f32, f32) {
+        match self {
+            Difficulty::Easy => (30.0, 2.5),
+            Difficulty::Normal => (20.0, 2.0),
+            Difficulty::Hard => (12.0, 1.5),
+        }
+    }
+
+    // cells on top of the shortest start-to-goal distance the stream has to fill
+    fn extra_length(&self) -> u32 {
+        match self {
+            Difficulty::Easy => 0,
+            Difficulty::Normal => 2,
+            Difficulty::Hard => 4,
+        }
+    }
+
+    fn queue_weights(&self) -> TileQueueWeights {
+        match self {
+            Difficulty::Easy => vec![
+                (BlockKeys::Router1Straight, 4),
+                (BlockKeys::Router1Corner, 4),
+                (BlockKeys::Router1Cross, 1),
+            ],
+            Difficulty::Normal => vec![
+                (BlockKeys::Router1Straight, 3),
+                (BlockKeys::Router1Corner, 3),
+                (BlockKeys::Router1Cross, 1),
+                (BlockKeys::Router1Tee, 1),
+            ],
+            Difficulty::Hard => vec![
+                (BlockKeys::Router1Straight, 3),
+                (BlockKeys::Router1Corner, 3),
+                (BlockKeys::Router1Cross, 2),
+                (BlockKeys::Router1Tee, 2),
+                (BlockKeys::RouteJoin2To1, 1),
+            ],
+        }
+    }
+}
+
+impl std::str::FromStr for Difficulty {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Difficulty::ALL
+            .into_iter()
+            .find(|difficulty| difficulty.as_str().eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeneratorSettings {
+    pub width: i32,
+    pub height: i32,
+    pub difficulty: Difficulty,
+    pub topology: GridTopology,
+    pub seed: u64,
+    pub max_attempts: u32,
+    pub queue_size: usize, // the queue the solver checks the level with
+    pub solver: SolverSettings,
+}
+
+impl Default for GeneratorSettings {
+    fn default() -> Self {
+        GeneratorSettings {
+            width: 7,
+            height: 9,
+            difficulty: Difficulty::Normal,
+            topology: GridTopology::default(),
+            seed: 0,
+            max_attempts: 50,
+            queue_size: 5,
+            solver: SolverSettings {
+                max_nodes: 50_000,
+                ..SolverSettings::default()
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeneratorError {
+    GridTooSmall,
+    NoSolvableLevel { attempts: u32 },
+}
+
+impl std::fmt::Display for GeneratorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GeneratorError::GridTooSmall => write!(f, "grid must be at least 3 x 3"),
+            GeneratorError::NoSolvableLevel { attempts } => {
+                write!(f, "no solvable level after {} attempts", attempts)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratedLevel {
+    pub level: Level,
+    pub report: SolveReport, // what the solver found for it
+    pub attempts: u32,
+}
+
+const OBSTACLES: [BlockKeys; 4] = [
+    BlockKeys::LineBlock1Edge,
+    BlockKeys::LineBlock2Corner,
+    BlockKeys::LineBlock3T,
+    BlockKeys::LineBlock4All,
+];
+
+const PRE_PLACED: [BlockKeys; 3] = [
+    BlockKeys::Router1Straight,
+    BlockKeys::Router1Corner,
+    BlockKeys::Router1Cross,
+];
+
+fn random_position(rng: &mut SeededRng, width: i32, height: i32) -> GridPosition {
+    GridPosition::new(
+        rng.next_below(width as u64) as i32,
+        rng.next_below(height as u64) as i32,
+    )
+}
+
+fn random_orientation(rng: &mut SeededRng) -> Orientation {
+    Orientation::new(
+        Rotation::from_quarter_turns(rng.next_below(4) as i32),
+        rng.next_bool(),
+    )
+}
+
+// One candidate level, not checked for solvability yet
+fn build_level(settings: &GeneratorSettings, rng: &mut SeededRng) -> Level {
+    let (width, height) = (settings.width, settings.height);
+    let difficulty = settings.difficulty;
+    let topology = settings.topology;
+    let in_grid = |p: GridPosition| p.x >= 0 && p.y >= 0 && p.x < width && p.y < height;
+
+    // start, with the stream heading into the grid
+    let start_position = random_position(rng, width, height);
+    let exits: Vec<Direction> = Direction::ALL
+        .into_iter()
+        .filter(|exit| in_grid(topology.port_neighbour(start_position, *exit)))
+        .collect();
+    let exit = exits[rng.next_below(exits.len() as u64) as usize];
+    let first_cell = topology.port_neighbour(start_position, exit);
+
+    // goal, at least half way across the grid (further for harder levels)
+    let min_distance = match difficulty {
+        Difficulty::Easy => (width + height) / 3,
+        Difficulty::Normal => (width + height) / 2,
+        Difficulty::Hard => (width + height) * 2 / 3,
+    };
+    let mut goal = random_position(rng, width, height);
+    for _ in 0..100 {
+        if goal != start_position
+            && goal != first_cell
+            && topology.distance(goal, start_position) >= min_distance
+        {
+            break;
+        }
+        goal = random_position(rng, width, height);
+    }
+
+    // everything else goes on cells that are still free (keeping the one in front of the
+    // start free, or the stream would be blocked right away)
+    let mut taken = vec![start_position, goal, first_cell];
+    let mut cells = Vec::new();
+    let area = (width * height) as u64;
+    let obstacles = area * difficulty.obstacle_percent() / 100;
+    let (pre_placed, replaceable) = difficulty.pre_placed();
+    for index in 0..(obstacles + pre_placed) {
+        let position = random_position(rng, width, height);
+        if taken.contains(&position) {
+            continue;
+        }
+        taken.push(position);
+        let obstacle = index < obstacles;
+        let key = match obstacle {
+            true => OBSTACLES[rng.next_below(OBSTACLES.len() as u64) as usize],
+            false => PRE_PLACED[rng.next_below(PRE_PLACED.len() as u64) as usize],
+        };
+        cells.push(LevelCell {
+            position,
+            block: OrientedBlock::new(key, random_orientation(rng)),
+            locked: obstacle || !replaceable,
+        });
+    }
+
+    let (countdown_seconds, cell_seconds) = difficulty.timing();
+    let shortest = (topology.distance(start_position, goal) - 1).max(1) as u32;
+    Level {
+        version: LEVEL_FORMAT_VERSION,
+        name: format!(
+            "{} {} x {} seed {}",
+            difficulty.as_str(),
+            width,
+            height,
+            settings.seed
+        ),
+        width,
+        height,
+        topology,
+        countdown_seconds,
+        cell_seconds,
+        queue_seed: rng.next_u64(),
+        queue_weights: difficulty.queue_weights(),
+        starts: vec![LevelStart {
+            position: start_position,
+            exit,
+        }],
+        goals: vec![goal],
+        cells,
+        scoring: ScoringRules {
+            min_length: shortest + difficulty.extra_length(),
+            ..ScoringRules::default()
+        },
+        ..Level::default()
+    }
+}
+
+pub fn generate(settings: &GeneratorSettings) -> Result<GeneratedLevel, GeneratorError> {
+    if settings.width < 3 || settings.height < 3 {
+        return Err(GeneratorError::GridTooSmall);
+    }
+    let mut rng = SeededRng::new(settings.seed);
+    for attempt in 1..=settings.max_attempts {
+        let level = build_level(settings, &mut rng);
+        if level.validate().is_err() {
+            continue;
+        }
+        let queue = TileQueue::new(
+            settings.queue_size,
+            level.queue_seed,
+            level.queue_weights.clone(),
+        );
+        let report = match solve(&level, &queue, &settings.solver) {
+            Ok(report) => report,
+            Err(_) => continue,
+        };
+        if report.is_solvable() {
+            return Ok(GeneratedLevel {
+                level,
+                report,
+                attempts: attempt,
+            });
+        }
+    }
+    Err(GeneratorError::NoSolvableLevel {
+        attempts: settings.max_attempts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_levels_are_solvable_and_repeatable() {
+        for difficulty in Difficulty::ALL {
+            let settings = GeneratorSettings {
+                difficulty,
+                seed: 20240101,
+                ..GeneratorSettings::default()
+            };
+            let generated = generate(&settings).unwrap();
+            assert!(generated.report.is_solvable());
+            // written out and read back, it is the same level
+            let text = generated.level.to_text();
+            assert_eq!(Level::parse(&text).unwrap(), generated.level);
+            assert_eq!(generate(&settings).unwrap().level, generated.level);
+        }
+    }
+
+    #[test]
+    fn test_generated_levels_on_every_topology() {
+        for topology in GridTopology::ALL {
+            let settings = GeneratorSettings {
+                topology,
+                seed: 7,
+                ..GeneratorSettings::default()
+            };
+            let generated = generate(&settings).unwrap();
+            assert_eq!(generated.level.topology, topology);
+            assert!(generated.report.is_solvable());
+        }
+    }
+
+    #[test]
+    fn test_too_small() {
+        let settings = GeneratorSettings {
+            width: 2,
+            ..GeneratorSettings::default()
+        };
+        assert_eq!(generate(&settings), Err(GeneratorError::GridTooSmall));
+    }
+}
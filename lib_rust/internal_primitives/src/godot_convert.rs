@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+
+use godot::prelude::*;
+
+use crate::{BlockKeys, BlockUnitsMapType, CellIdType, GridPosition};
+
+// The thin layer between the (Godot-free) primitives and the Godot Engine types, only compiled
+// with the "godot" feature so that the core can be built/tested without the engine.
+// Rule-of-thumb: if it needs 'use godot::...', it goes here and NOT in the modules next to it.
+
+impl From<Vector2i> for GridPosition {
+    fn from(v: Vector2i) -> Self {
+        GridPosition::new(v.x, v.y)
+    }
+}
+impl From<GridPosition> for Vector2i {
+    fn from(pos: GridPosition) -> Self {
+        Vector2i::new(pos.x, pos.y)
+    }
+}
+
+// To be able to render it on the Godot editor (i.e. Inspector), we need to have a way to convert
+// the enum to a Godot primitive type (i.e. GString)
+// Once From<T> is implemented (conversion traits), you can then do "into()"
+// i.e. let gstring: GString = TileMapType::Undefined.into();
+impl From<BlockUnitsMapType> for GString {
+    fn from(tile_map_type: BlockUnitsMapType) -> Self {
+        tile_map_type.as_str().into()
+    }
+}
+impl TryFrom<GString> for BlockUnitsMapType {
+    type Error = ();
+
+    fn try_from(godot_string: GString) -> Result<Self, Self::Error> {
+        godot_string.to_string().parse()
+    }
+}
+
+// as much as I appreciate Tuples, they are anonymous and are ref'ed by positon (i.e. tup.0, tup.1, and tup.2, etc)
+// so I'll stick with struct for my KVP values in case it grows fatter than 2 elements...
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockUnitCellKVPValue {
+    pub source_id: CellIdType,
+    // id of the scene within its TileSetScenesCollectionSource, which is what TileMap takes as
+    // the alternative tile (atlas coords are always (0, 0) for scene collections)
+    pub tile_id: CellIdType,
+    pub scene: Option<Gd<PackedScene>>,
+    pub resource_path: Option<GString>, // use GString here?
+}
+// e.g. let mut my_dict: BlockUnitCellDictionaryType = HashMap::new();  // Key: BlockKeys, Value: BlockUnitCellKVPValue
+pub type BlockUnitCellDictionaryType = HashMap<BlockKeys, BlockUnitCellKVPValue>;
@@ -0,0 +1,352 @@
+use std::collections::HashSet;
+
+use crate::connectivity::{BlockPorts, Direction};
+use crate::flow::{FlowHead, FlowSimulator};
+use crate::orientation::{Orientation, OrientedBlock, Rotation};
+use crate::placement::check_placement;
+use crate::topology::GridTopology;
+use crate::{cell_at, cell_at_mut, BlockKeys, CellMap, GridPosition};
+
+// Hints for players stuck staring at the queue: where (and how) to put the head of the queue so
+// that the stream gets furthest.
+// For every empty cell the player may place on, the head piece is tried in each orientation,
+// and the stream is followed from the flow head: through whatever is already on the grid, and
+// on reaching an empty cell, through the next 'lookahead' pieces of the queue (each either
+// placed right there in its best orientation, or dumped elsewhere so the one after it can be
+// used).  A cell is worth as many cells as the stream reaches that way, over what it reaches
+// when the head piece is dumped instead.
+// NOTE: Splits are followed down every branch, but only the longest branch counts (same as a
+// single stream), and goals end the stream like in FlowSimulator.
+
+// Every empty cell the stream runs into branches over up to 8 orientations (plus the dump) per
+// lookahead piece, for every candidate cell, so the work grows very quickly past this; callers
+// taking the lookahead from the player (or a designer) should clamp it to this
+pub const MAX_LOOKAHEAD: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HintSettings {
+    pub lookahead: usize, // queue pieces after the head considered
+    pub topology: GridTopology,
+}
+
+impl Default for HintSettings {
+    fn default() -> Self {
+        HintSettings {
+            lookahead: 2,
+            topology: GridTopology::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CellHint {
+    pub head: FlowHead, // stream the piece is meant for
+    pub position: GridPosition,
+    pub orientation: Orientation, // best orientation of the head piece on this cell
+    pub reach: u32,               // cells the stream reaches with the piece there
+    pub extension: u32,           // cells more than without it
+}
+
+struct Walker<'a> {
+    cells: CellMap, // the grid plus whatever is being tried
+    flow: Option<&'a FlowSimulator>,
+    topology: GridTopology,
+    channels: HashSet<(GridPosition, Option<bool>)>, // filled while following the stream
+}
+
+// distinct ways 'key' can be put down (i.e. a straight only has two)
+fn orientations_of(key: BlockKeys) -> Vec<Orientation> {
+    let mut seen: Vec<BlockPorts> = Vec::new();
+    let mut orientations = Vec::new();
+    for index in 0..8 {
+        let orientation = Orientation::new(Rotation::from_quarter_turns(index % 4), index >= 4);
+        let ports = key.oriented_ports(orientation);
+        if !seen.contains(&ports) {
+            seen.push(ports);
+            orientations.push(orientation);
+        }
+    }
+    orientations
+}
+
+fn is_empty(key: BlockKeys) -> bool {
+    key == BlockKeys::Void || key == BlockKeys::Undefined
+}
+
+impl<'a> Walker<'a> {
+    // cells the stream reaches after leaving 'head', with 'upcoming' pieces still to come
+    fn reach(&mut self, head: FlowHead, upcoming: &[BlockKeys]) -> u32 {
+        let target = self.topology.port_neighbour(head.position, head.exit);
+        let entry = head.exit.opposite();
+        let cell = match cell_at(&self.cells, target) {
+            Some(cell) => *cell,
+            None => return 0,
+        };
+        if cell.flags.goal {
+            return 1;
+        }
+        if !is_empty(cell.key) {
+            return self.pass_through(target, entry, cell.ports(), upcoming);
+        }
+        let (next, rest) = match upcoming.split_first() {
+            Some(split) if check_placement(&self.cells, target).is_ok() => split,
+            _ => return 0,
+        };
+        // dump 'next' somewhere else, and go on with the piece after it
+        let mut best = self.reach(head, rest);
+        for orientation in orientations_of(*next) {
+            let block = OrientedBlock::new(*next, orientation);
+            cell_at_mut(&mut self.cells, target).unwrap().place(block);
+            best = best.max(self.pass_through(target, entry, block.ports(), rest));
+        }
+        *cell_at_mut(&mut self.cells, target).unwrap() = cell;
+        best
+    }
+
+    // same rules as FlowSimulator::tick()
+    fn pass_through(
+        &mut self,
+        target: GridPosition,
+        entry: Direction,
+        ports: BlockPorts,
+        upcoming: &[BlockKeys],
+    ) -> u32 {
+        if !matches!(ports.port(entry), Some(flow) if flow.accepts()) {
+            return 0;
+        }
+        let channel = match ports.is_crossing() {
+            true => Some(matches!(entry, Direction::North | Direction::South)),
+            false => None,
+        };
+        let filled = self
+            .flow
+            .map(|flow| flow.is_channel_filled(target, channel))
+            .unwrap_or(false);
+        if filled || !self.channels.insert((target, channel)) {
+            return 0;
+        }
+        let best = ports
+            .exits_for(entry)
+            .into_iter()
+            .map(|exit| {
+                self.reach(
+                    FlowHead {
+                        position: target,
+                        exit,
+                    },
+                    upcoming,
+                )
+            })
+            .max()
+            .unwrap_or(0);
+        self.channels.remove(&(target, channel));
+        1 + best
+    }
+}
+
+// Cells worth putting queue[0] on, best first; only cells that make the stream reach further
+// are returned, so an empty result means "dump it anywhere".
+// 'head' is where the stream is about to leave from (the start before the stream started),
+// 'flow' the stream so far (if started), and 'queue' the queue contents, head first.
+pub fn rank_cells(
+    cells: &CellMap,
+    head: FlowHead,
+    flow: Option<&FlowSimulator>,
+    queue: &[BlockKeys],
+    settings: &HintSettings,
+) -> Vec<CellHint> {
+    let (piece, rest) = match queue.split_first() {
+        Some(split) => split,
+        None => return Vec::new(),
+    };
+    let upcoming = &rest[..settings.lookahead.min(rest.len())];
+    let mut walker = Walker {
+        cells: cells.clone(),
+        flow,
+        topology: settings.topology,
+        channels: HashSet::new(),
+    };
+    let without = walker.reach(head, upcoming);
+
+    let candidates: Vec<GridPosition> = cells
+        .iter()
+        .flatten()
+        .flatten()
+        .filter(|cell| is_empty(cell.key))
+        .map(|cell| cell.position)
+        .filter(|position| check_placement(cells, *position).is_ok())
+        .filter(|position| !flow.map(|flow| flow.is_reached(*position)).unwrap_or(false))
+        .collect();
+    let mut hints = Vec::new();
+    for position in candidates {
+        let before = *cell_at(&walker.cells, position).unwrap();
+        let mut best: Option<CellHint> = None;
+        for orientation in orientations_of(*piece) {
+            cell_at_mut(&mut walker.cells, position)
+                .unwrap()
+                .place(OrientedBlock::new(*piece, orientation));
+            let reach = walker.reach(head, upcoming);
+            if reach > without && best.map(|best| reach > best.reach).unwrap_or(true) {
+                best = Some(CellHint {
+                    head,
+                    position,
+                    orientation,
+                    reach,
+                    extension: reach - without,
+                });
+            }
+        }
+        *cell_at_mut(&mut walker.cells, position).unwrap() = before;
+        hints.extend(best);
+    }
+    // stable sort, so equally good cells stay in grid order
+    hints.sort_by_key(|hint| std::cmp::Reverse(hint.extension));
+    hints
+}
+
+// Same as rank_cells(), for every head (i.e. each start before the stream begins, or each
+// branch of a split stream) in one list; a cell good for more than one of them is in it once
+// per head
+pub fn rank_cells_for_heads(
+    cells: &CellMap,
+    heads: &[FlowHead],
+    flow: Option<&FlowSimulator>,
+    queue: &[BlockKeys],
+    settings: &HintSettings,
+) -> Vec<CellHint> {
+    let mut hints: Vec<CellHint> = heads
+        .iter()
+        .flat_map(|head| rank_cells(cells, *head, flow, queue, settings))
+        .collect();
+    // stable sort, so equally good cells stay in head order
+    hints.sort_by_key(|hint| std::cmp::Reverse(hint.extension));
+    hints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::{Level, LevelStart};
+
+    // S . . . G across the middle row
+    fn row_level() -> Level {
+        Level {
+            version: 1,
+            width: 5,
+            height: 3,
+            starts: vec![LevelStart {
+                position: GridPosition::new(0, 1),
+                exit: Direction::East,
+            }],
+            goals: vec![GridPosition::new(4, 1)],
+            ..Level::default()
+        }
+    }
+
+    #[test]
+    fn test_head_piece_goes_in_front_of_the_stream() {
+        let level = row_level();
+        let cells = level.to_cell_map(0);
+        let head = FlowHead {
+            position: GridPosition::new(0, 1),
+            exit: Direction::East,
+        };
+        let queue = [
+            BlockKeys::Router1Straight,
+            BlockKeys::Router1Straight,
+            BlockKeys::Router1Straight,
+        ];
+
+        // without lookahead only the cell right in front of the start is any good
+        let settings = HintSettings {
+            lookahead: 0,
+            ..HintSettings::default()
+        };
+        let hints = rank_cells(&cells, head, None, &queue, &settings);
+        assert_eq!(hints.len(), 1);
+        assert_eq!(hints[0].position, GridPosition::new(1, 1));
+        assert_eq!(hints[0].extension, 1);
+        assert!(OrientedBlock::new(queue[0], hints[0].orientation)
+            .ports()
+            .is_open(Direction::West));
+
+        // with the next two pieces, the row can be filled all the way to the goal, and the
+        // head piece can go on any of the three cells
+        let settings = HintSettings::default();
+        let hints = rank_cells(&cells, head, None, &queue, &settings);
+        let positions: Vec<GridPosition> = hints.iter().map(|hint| hint.position).collect();
+        assert_eq!(
+            positions,
+            vec![
+                GridPosition::new(1, 1),
+                GridPosition::new(2, 1),
+                GridPosition::new(3, 1)
+            ]
+        );
+        assert!(hints.iter().all(|hint| hint.reach == 4));
+    }
+
+    #[test]
+    fn test_nothing_helps() {
+        let level = row_level();
+        let cells = level.to_cell_map(0);
+        let head = FlowHead {
+            position: GridPosition::new(0, 1),
+            exit: Direction::East,
+        };
+        // an obstacle at the head of the queue never gets the stream any further
+        let queue = [BlockKeys::LineBlock4All];
+        let hints = rank_cells(&cells, head, None, &queue, &HintSettings::default());
+        assert!(hints.is_empty());
+    }
+
+    #[test]
+    fn test_every_head_gets_hints() {
+        // two starts, along the top and the bottom row
+        let level = Level {
+            starts: vec![
+                LevelStart {
+                    position: GridPosition::new(0, 0),
+                    exit: Direction::East,
+                },
+                LevelStart {
+                    position: GridPosition::new(0, 2),
+                    exit: Direction::East,
+                },
+            ],
+            goals: Vec::new(),
+            ..row_level()
+        };
+        let cells = level.to_cell_map(0);
+        let heads: Vec<FlowHead> = level
+            .starts
+            .iter()
+            .map(|start| FlowHead {
+                position: start.position,
+                exit: start.exit,
+            })
+            .collect();
+        let settings = HintSettings {
+            lookahead: 0,
+            ..HintSettings::default()
+        };
+        let hints = rank_cells_for_heads(
+            &cells,
+            &heads,
+            None,
+            &[BlockKeys::Router1Straight],
+            &settings,
+        );
+        let found: Vec<(FlowHead, GridPosition)> = hints
+            .iter()
+            .map(|hint| (hint.head, hint.position))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (heads[0], GridPosition::new(1, 0)),
+                (heads[1], GridPosition::new(1, 2))
+            ]
+        );
+    }
+}
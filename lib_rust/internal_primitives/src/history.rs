@@ -0,0 +1,222 @@
+use crate::flow::FlowSimulator;
+use crate::placement::PlacementOutcome;
+use crate::tile_queue::TileQueue;
+use crate::{cell_at_mut, BlockUnitCell, CellMap, GridPosition};
+
+// Undo/redo of the player's placements.
+// Each placement is recorded with the whole cell before/after (key, orientation AND flags), and
+// if the piece came off the queue, a snapshot of the queue before/after as well; the queue has
+// to be snapshotted (rather than "push the piece back to the head") because popping the head
+// refills the tail with a random piece, and the seeded rng state has to go back with it or
+// the player could re-roll the queue by undoing.
+// Rules:
+// * a cell the stream has already reached cannot be undone (nor redone), the stream is in it
+// * the number of undos can be limited per level ('undo_budget' in level.rs)
+// * penalties of a replacement are NOT given back on undo (or else undo would be a free bomb)
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlacementCommand {
+    pub position: GridPosition,
+    pub before: BlockUnitCell,
+    pub after: BlockUnitCell,
+    pub queue_before: Option<TileQueue>,
+    pub queue_after: Option<TileQueue>,
+    pub outcome: PlacementOutcome,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UndoError {
+    NothingToUndo,
+    NothingToRedo,
+    BudgetExhausted,
+    FlowReached, // stream is already in the cell
+}
+
+impl UndoError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UndoError::NothingToUndo => "nothing_to_undo",
+            UndoError::NothingToRedo => "nothing_to_redo",
+            UndoError::BudgetExhausted => "budget_exhausted",
+            UndoError::FlowReached => "flow_reached",
+        }
+    }
+}
+
+impl std::fmt::Display for UndoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PlacementHistory {
+    undo_stack: Vec<PlacementCommand>,
+    redo_stack: Vec<PlacementCommand>,
+    budget: Option<u32>, // None is unlimited
+    undos_used: u32,
+}
+
+fn write_back(cells: &mut CellMap, cell: &BlockUnitCell) {
+    if let Some(target) = cell_at_mut(cells, cell.position) {
+        *target = *cell;
+    }
+}
+
+impl PlacementHistory {
+    pub fn new(budget: Option<u32>) -> Self {
+        PlacementHistory {
+            budget,
+            ..Default::default()
+        }
+    }
+
+    // undos left, None if unlimited
+    pub fn undos_left(&self) -> Option<u32> {
+        self.budget
+            .map(|budget| budget.saturating_sub(self.undos_used))
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty() && self.undos_left() != Some(0)
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    // a new placement invalidates whatever could have been redone
+    pub fn record(&mut self, command: PlacementCommand) {
+        self.undo_stack.push(command);
+        self.redo_stack.clear();
+    }
+
+    // Puts the cell (and the queue, if the piece came off it) back the way it was before the
+    // last placement; returns the command so the caller can update whatever it draws
+    pub fn undo(
+        &mut self,
+        cells: &mut CellMap,
+        queue: Option<&mut TileQueue>,
+        flow: Option<&FlowSimulator>,
+    ) -> Result<PlacementCommand, UndoError> {
+        let command = self.undo_stack.last().ok_or(UndoError::NothingToUndo)?;
+        if self.undos_left() == Some(0) {
+            return Err(UndoError::BudgetExhausted);
+        }
+        if flow.map(|flow| flow.is_reached(command.position)).unwrap_or(false) {
+            return Err(UndoError::FlowReached);
+        }
+        let command = self.undo_stack.pop().unwrap();
+        write_back(cells, &command.before);
+        if let (Some(queue), Some(snapshot)) = (queue, command.queue_before.as_ref()) {
+            *queue = snapshot.clone();
+        }
+        self.undos_used += 1;
+        self.redo_stack.push(command.clone());
+        Ok(command)
+    }
+
+    // Re-applies the last undone placement (does not count against the budget)
+    pub fn redo(
+        &mut self,
+        cells: &mut CellMap,
+        queue: Option<&mut TileQueue>,
+        flow: Option<&FlowSimulator>,
+    ) -> Result<PlacementCommand, UndoError> {
+        let command = self.redo_stack.last().ok_or(UndoError::NothingToRedo)?;
+        if flow.map(|flow| flow.is_reached(command.position)).unwrap_or(false) {
+            return Err(UndoError::FlowReached);
+        }
+        let command = self.redo_stack.pop().unwrap();
+        write_back(cells, &command.after);
+        if let (Some(queue), Some(snapshot)) = (queue, command.queue_after.as_ref()) {
+            *queue = snapshot.clone();
+        }
+        self.undo_stack.push(command.clone());
+        Ok(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cell_at;
+    use crate::connectivity::Direction;
+    use crate::orientation::{OrientedBlock, Orientation, Rotation};
+    use crate::test_utils::make_map;
+    use crate::BlockKeys;
+
+    // takes the head of the queue and places it at position, recording it
+    fn place_from_queue(
+        history: &mut PlacementHistory,
+        cells: &mut CellMap,
+        queue: &mut TileQueue,
+        position: GridPosition,
+    ) {
+        let before = *cell_at(cells, position).unwrap();
+        let queue_before = queue.clone();
+        let head = queue.get_head();
+        let mut after = before;
+        after.place(OrientedBlock::new(head.key, Orientation::new(Rotation::Deg90, false)));
+        write_back(cells, &after);
+        history.record(PlacementCommand {
+            position,
+            before,
+            after,
+            queue_before: Some(queue_before),
+            queue_after: Some(queue.clone()),
+            outcome: PlacementOutcome::Placed,
+        });
+    }
+
+    #[test]
+    fn test_undo_redo_restores_queue() {
+        let mut cells = make_map(3, 1, &[]);
+        let mut queue = TileQueue::new(3, 99, vec![(BlockKeys::Router1Straight, 1)]);
+        let mut history = PlacementHistory::new(None);
+        let position = GridPosition::new(1, 0);
+        let queue_at_start = queue.clone();
+
+        place_from_queue(&mut history, &mut cells, &mut queue, position);
+        let queue_after_place = queue.clone();
+        assert_eq!(cell_at(&cells, position).unwrap().key, BlockKeys::Router1Straight);
+
+        history.undo(&mut cells, Some(&mut queue), None).unwrap();
+        assert_eq!(cell_at(&cells, position).unwrap().key, BlockKeys::Void);
+        assert_eq!(queue, queue_at_start);
+
+        history.redo(&mut cells, Some(&mut queue), None).unwrap();
+        assert_eq!(cell_at(&cells, position).unwrap().key, BlockKeys::Router1Straight);
+        assert_eq!(queue, queue_after_place);
+        assert_eq!(
+            history.redo(&mut cells, Some(&mut queue), None),
+            Err(UndoError::NothingToRedo)
+        );
+    }
+
+    #[test]
+    fn test_budget_and_flow() {
+        let mut cells = make_map(3, 1, &[]);
+        let mut queue = TileQueue::new(3, 1, vec![(BlockKeys::Router1Straight, 1)]);
+        let mut history = PlacementHistory::new(Some(1));
+        place_from_queue(&mut history, &mut cells, &mut queue, GridPosition::new(1, 0));
+        place_from_queue(&mut history, &mut cells, &mut queue, GridPosition::new(2, 0));
+
+        // stream flows east from (0,0) into the first piece
+        let mut flow = FlowSimulator::new(GridPosition::new(0, 0), Direction::East);
+        flow.tick(&cells);
+        assert!(history.undo(&mut cells, None, Some(&flow)).is_ok());
+        assert_eq!(history.undos_left(), Some(0));
+        assert_eq!(
+            history.undo(&mut cells, None, None),
+            Err(UndoError::BudgetExhausted)
+        );
+
+        let mut history = PlacementHistory::new(None);
+        place_from_queue(&mut history, &mut cells, &mut queue, GridPosition::new(1, 0));
+        assert_eq!(
+            history.undo(&mut cells, None, Some(&flow)),
+            Err(UndoError::FlowReached)
+        );
+    }
+}
@@ -0,0 +1,686 @@
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::clock::ClockSettings;
+use crate::connectivity::Direction;
+use crate::flow::{FlowHead, FlowSimulator, SplitMode};
+use crate::orientation::{OrientedBlock, Orientation, Rotation};
+use crate::placement::ReplacementRules;
+use crate::scoring::ScoringRules;
+use crate::tile_queue::{TileQueue, TileQueueWeights};
+use crate::topology::GridTopology;
+use crate::{BlockKeys, BlockUnitCell, CellFlags, CellMap, GridPosition, LayerType};
+
+// Human-editable level file, one "key = value" per line, '#' starts a comment (except on the
+// name line, which is taken as-is so that names like "Level #3" survive a save/load), i.e.:
+//
+//      # my first level
+//      version = 1
+//      name = Tutorial 1
+//      size = 8 x 6
+//      topology = square           # square, iso_diamond, hex_offset or hex_axial (see topology.rs)
+//      countdown = 20.0            # seconds before the stream begins to flow
+//      cell_seconds = 2.0          # seconds it takes the stream to fill one cell
+//      fast_cell_seconds = 0.1     # same, once the player fast-forwards
+//      queue_seed = 1234
+//      queue_weights = Router1Straight:3, Router1Corner:2, Router1Cross:1
+//      start = 0,2 East            # position, and the side the stream leaves through
+//      start = 0,4 East            # (every start has its own stream)
+//      goal = 7,2
+//      goal = 7,5 fill 2.0         # stream volume the goal needs to be won (1.0 per start)
+//      split = duplicate           # Router1Tee/Router: 'duplicate' or 'divide' the volume
+//      tower = 3,3
+//      cell = 3,1 Router1Straight 90 locked    # position, BlockKeys [rotation] [flip] [locked]
+//      cell = 4,4 LineBlock4All locked
+//      replace_allowed = true      # can the player place over (non-locked) pieces?
+//      replace_delay = 1.0         # seconds the player cannot place after a replacement
+//      replace_penalty = 50        # points taken off per replacement
+//      score_per_cell = 50         # points per cell the stream fills
+//      score_cross_bonus = 500     # points for filling both channels of a Router1Cross
+//      score_unused_penalty = 100  # points taken off per placed piece the stream never reached
+//      min_length = 10             # cells the stream has to fill to win
+//      undo_budget = 3             # undos the player gets ('unlimited' if not set)
+//
+// Keys can be in any order (except that version MUST be set), cells that are not listed are Void.
+// Unknown keys are an error rather than ignored so that typos do not silently produce a
+// different level.
+// NOTE: Bump LEVEL_FORMAT_VERSION whenever a key changes meaning, and keep parse() able to
+// read the older versions.
+pub const LEVEL_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LevelCell {
+    pub position: GridPosition,
+    pub block: OrientedBlock,
+    pub locked: bool, // pre-placed and cannot be replaced by the player
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LevelStart {
+    pub position: GridPosition,
+    pub exit: Direction, // the side the stream leaves the start cell through
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Level {
+    pub version: u32,
+    pub name: String,
+    pub width: i32,
+    pub height: i32,
+    pub topology: GridTopology,
+    pub countdown_seconds: f32,
+    pub cell_seconds: f32,
+    pub fast_cell_seconds: f32,
+    pub queue_seed: u64,
+    pub queue_weights: TileQueueWeights,
+    pub starts: Vec<LevelStart>,
+    pub goals: Vec<GridPosition>,
+    pub goal_fills: Vec<(GridPosition, f32)>, // only the goals that require a volume
+    pub split_mode: SplitMode,
+    pub towers: Vec<GridPosition>,
+    pub cells: Vec<LevelCell>, // only the pre-placed ones
+    pub replacement: ReplacementRules,
+    pub scoring: ScoringRules,
+    pub undo_budget: Option<u32>, // None is unlimited
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LevelError {
+    Io(String),
+    Parse { line: usize, message: String }, // line is 1-based
+    Invalid(String),                         // parsed fine, but does not make sense as a level
+}
+
+impl std::fmt::Display for LevelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LevelError::Io(message) => write!(f, "I/O error: {}", message),
+            LevelError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            LevelError::Invalid(message) => write!(f, "invalid level: {}", message),
+        }
+    }
+}
+
+impl Default for Level {
+    fn default() -> Self {
+        Level {
+            version: LEVEL_FORMAT_VERSION,
+            name: String::new(),
+            width: 0,
+            height: 0,
+            topology: GridTopology::default(),
+            countdown_seconds: 0.0,
+            cell_seconds: ClockSettings::default().seconds_per_cell,
+            fast_cell_seconds: ClockSettings::default().fast_seconds_per_cell,
+            queue_seed: 0,
+            queue_weights: TileQueue::default_weights(),
+            starts: Vec::new(),
+            goals: Vec::new(),
+            goal_fills: Vec::new(),
+            split_mode: SplitMode::default(),
+            towers: Vec::new(),
+            cells: Vec::new(),
+            replacement: ReplacementRules::default(),
+            scoring: ScoringRules::default(),
+            undo_budget: None,
+        }
+    }
+}
+
+pub(crate) fn parse_position(text: &str) -> Result<GridPosition, String> {
+    let (x, y) = text
+        .split_once(',')
+        .ok_or_else(|| format!("expected 'x,y' but got '{}'", text))?;
+    let x = x.trim().parse::<i32>().map_err(|e| format!("bad x '{}': {}", x, e))?;
+    let y = y.trim().parse::<i32>().map_err(|e| format!("bad y '{}': {}", y, e))?;
+    Ok(GridPosition::new(x, y))
+}
+
+pub(crate) fn parse_block_key(text: &str) -> Result<BlockKeys, String> {
+    text.parse::<BlockKeys>()
+        .map_err(|_| format!("unknown block '{}'", text))
+}
+
+fn parse_direction(text: &str) -> Result<Direction, String> {
+    text.parse::<Direction>()
+        .map_err(|_| format!("unknown direction '{}' (North, East, South or West)", text))
+}
+
+// "Block:weight, Block:weight, ..."
+pub(crate) fn parse_queue_weights(text: &str) -> Result<TileQueueWeights, String> {
+    let mut weights = Vec::new();
+    for pair in text.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (key, weight) = pair
+            .split_once(':')
+            .ok_or_else(|| format!("expected 'Block:weight' but got '{}'", pair))?;
+        let key = parse_block_key(key.trim())?;
+        let weight = weight
+            .trim()
+            .parse()
+            .map_err(|e| format!("bad weight for {}: {}", key.as_str(), e))?;
+        weights.push((key, weight));
+    }
+    Ok(weights)
+}
+
+pub(crate) fn queue_weights_to_text(weights: &TileQueueWeights) -> String {
+    let weights: Vec<String> = weights
+        .iter()
+        .map(|(key, weight)| format!("{}:{}", key.as_str(), weight))
+        .collect();
+    weights.join(", ")
+}
+
+// one of the optional "[rotation] [flip]" tokens that follow a block
+pub(crate) fn parse_orientation_token(
+    orientation: &mut Orientation,
+    token: &str,
+) -> Result<(), String> {
+    if token == "flip" {
+        orientation.flipped = true;
+        return Ok(());
+    }
+    let degrees: i32 = token
+        .parse()
+        .map_err(|_| format!("unknown option '{}'", token))?;
+    if degrees % 90 != 0 {
+        return Err(format!("rotation {} is not a multiple of 90", degrees));
+    }
+    orientation.rotation = Rotation::from_quarter_turns(degrees / 90);
+    Ok(())
+}
+
+// the "[rotation] [flip]" tokens (with a leading space), empty if canonical
+pub(crate) fn orientation_to_text(orientation: Orientation) -> String {
+    let mut text = String::new();
+    if orientation.rotation != Rotation::Deg0 {
+        text += &format!(" {}", orientation.rotation_degrees());
+    }
+    if orientation.flipped {
+        text += " flip";
+    }
+    text
+}
+
+impl Level {
+    pub fn load(path: &Path) -> Result<Level, LevelError> {
+        let text = std::fs::read_to_string(path).map_err(|e| LevelError::Io(e.to_string()))?;
+        Level::parse(&text)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), LevelError> {
+        std::fs::write(path, self.to_text()).map_err(|e| LevelError::Io(e.to_string()))
+    }
+
+    pub fn parse(text: &str) -> Result<Level, LevelError> {
+        let mut level = Level {
+            version: 0, // MUST be explicitly set by the file
+            ..Level::default()
+        };
+        for (index, raw_line) in text.lines().enumerate() {
+            let line = raw_line.trim();
+            let line = match line.split_once('=') {
+                Some((key, _)) if key.trim() == "name" => line,
+                _ => line.split('#').next().unwrap_or("").trim(),
+            };
+            if line.is_empty() {
+                continue;
+            }
+            level
+                .parse_line(line)
+                .map_err(|message| LevelError::Parse {
+                    line: index + 1,
+                    message,
+                })?;
+        }
+        level.validate()?;
+        Ok(level)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("expected 'key = value' but got '{}'", line))?;
+        let value = value.trim();
+        match key.trim() {
+            "version" => {
+                self.version = value.parse().map_err(|e| format!("bad version: {}", e))?;
+                if self.version == 0 || self.version > LEVEL_FORMAT_VERSION {
+                    return Err(format!(
+                        "unsupported version {} (newest supported is {})",
+                        self.version, LEVEL_FORMAT_VERSION
+                    ));
+                }
+            }
+            "name" => self.name = value.to_string(),
+            "size" => {
+                let (w, h) = value
+                    .split_once('x')
+                    .ok_or_else(|| format!("expected 'width x height' but got '{}'", value))?;
+                self.width = w.trim().parse().map_err(|e| format!("bad width: {}", e))?;
+                self.height = h.trim().parse().map_err(|e| format!("bad height: {}", e))?;
+            }
+            "topology" => {
+                self.topology = value.parse().map_err(|_| {
+                    format!(
+                        "unknown topology '{}' (square, iso_diamond, hex_offset or hex_axial)",
+                        value
+                    )
+                })?
+            }
+            "countdown" => {
+                self.countdown_seconds =
+                    value.parse().map_err(|e| format!("bad countdown: {}", e))?
+            }
+            "cell_seconds" => {
+                self.cell_seconds = value.parse().map_err(|e| format!("bad cell_seconds: {}", e))?
+            }
+            "fast_cell_seconds" => {
+                self.fast_cell_seconds =
+                    value.parse().map_err(|e| format!("bad fast_cell_seconds: {}", e))?
+            }
+            "queue_seed" => {
+                self.queue_seed = value.parse().map_err(|e| format!("bad queue_seed: {}", e))?
+            }
+            "queue_weights" => self.queue_weights = parse_queue_weights(value)?,
+            "start" => {
+                let mut tokens = value.split_whitespace();
+                let position = parse_position(tokens.next().unwrap_or(""))?;
+                let exit = parse_direction(tokens.next().ok_or("start is missing a direction")?)?;
+                if let Some(token) = tokens.next() {
+                    return Err(format!("start: unexpected '{}'", token));
+                }
+                self.starts.push(LevelStart { position, exit });
+            }
+            "goal" => {
+                let mut tokens = value.split_whitespace();
+                let position = parse_position(tokens.next().unwrap_or(""))?;
+                match (tokens.next(), tokens.next()) {
+                    (None, _) => {}
+                    (Some("fill"), Some(volume)) => {
+                        let volume = volume.parse().map_err(|e| format!("bad goal fill: {}", e))?;
+                        self.goal_fills.push((position, volume));
+                    }
+                    (Some("fill"), None) => return Err("goal fill is missing a volume".to_string()),
+                    (Some(token), _) => return Err(format!("goal: unknown option '{}'", token)),
+                }
+                if let Some(token) = tokens.next() {
+                    return Err(format!("goal: unexpected '{}'", token));
+                }
+                self.goals.push(position);
+            }
+            "split" => {
+                self.split_mode = value.parse().map_err(|_| {
+                    format!("unknown split '{}' (duplicate or divide)", value)
+                })?
+            }
+            "tower" => self.towers.push(parse_position(value)?),
+            "cell" => {
+                let mut tokens = value.split_whitespace();
+                let position = parse_position(tokens.next().unwrap_or(""))?;
+                let key = parse_block_key(tokens.next().ok_or("cell is missing a block")?)?;
+                let mut orientation = Orientation::default();
+                let mut locked = false;
+                for token in tokens {
+                    match token {
+                        "locked" => locked = true,
+                        token => parse_orientation_token(&mut orientation, token)
+                            .map_err(|e| format!("cell: {}", e))?,
+                    }
+                }
+                self.cells.push(LevelCell {
+                    position,
+                    block: OrientedBlock::new(key, orientation),
+                    locked,
+                });
+            }
+            "replace_allowed" => {
+                self.replacement.allowed =
+                    value.parse().map_err(|e| format!("bad replace_allowed: {}", e))?
+            }
+            "replace_delay" => {
+                self.replacement.delay_seconds =
+                    value.parse().map_err(|e| format!("bad replace_delay: {}", e))?
+            }
+            "replace_penalty" => {
+                self.replacement.point_penalty =
+                    value.parse().map_err(|e| format!("bad replace_penalty: {}", e))?
+            }
+            "score_per_cell" => {
+                self.scoring.points_per_cell =
+                    value.parse().map_err(|e| format!("bad score_per_cell: {}", e))?
+            }
+            "score_cross_bonus" => {
+                self.scoring.cross_bonus =
+                    value.parse().map_err(|e| format!("bad score_cross_bonus: {}", e))?
+            }
+            "score_unused_penalty" => {
+                self.scoring.unused_piece_penalty =
+                    value.parse().map_err(|e| format!("bad score_unused_penalty: {}", e))?
+            }
+            "min_length" => {
+                self.scoring.min_length =
+                    value.parse().map_err(|e| format!("bad min_length: {}", e))?
+            }
+            "undo_budget" => {
+                self.undo_budget = match value {
+                    "unlimited" => None,
+                    _ => Some(value.parse().map_err(|e| format!("bad undo_budget: {}", e))?),
+                }
+            }
+            unknown => return Err(format!("unknown key '{}'", unknown)),
+        }
+        Ok(())
+    }
+
+    fn in_bounds(&self, position: GridPosition) -> bool {
+        position.x >= 0 && position.y >= 0 && position.x < self.width && position.y < self.height
+    }
+
+    pub fn validate(&self) -> Result<(), LevelError> {
+        if self.version == 0 {
+            return Err(LevelError::Invalid("missing 'version'".to_string()));
+        }
+        if self.width <= 0 || self.height <= 0 {
+            return Err(LevelError::Invalid(format!(
+                "size must be at least 1 x 1 (got {} x {})",
+                self.width, self.height
+            )));
+        }
+        let mut positions: Vec<(&str, GridPosition)> = Vec::new();
+        positions.extend(self.starts.iter().map(|s| ("start", s.position)));
+        positions.extend(self.goals.iter().map(|g| ("goal", *g)));
+        positions.extend(self.towers.iter().map(|t| ("tower", *t)));
+        positions.extend(self.cells.iter().map(|c| ("cell", c.position)));
+        for (index, (what, position)) in positions.iter().enumerate() {
+            if !self.in_bounds(*position) {
+                return Err(LevelError::Invalid(format!(
+                    "{} ({}, {}) is outside of the {} x {} grid",
+                    what, position.x, position.y, self.width, self.height
+                )));
+            }
+            if let Some((other, _)) = positions[..index].iter().find(|(_, p)| p == position) {
+                return Err(LevelError::Invalid(format!(
+                    "{} ({}, {}) is on the same cell as a {}",
+                    what, position.x, position.y, other
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        // NOTE: writing into a String never fails, hence the unwraps
+        writeln!(text, "version = {}", self.version).unwrap();
+        if !self.name.is_empty() {
+            writeln!(text, "name = {}", self.name).unwrap();
+        }
+        writeln!(text, "size = {} x {}", self.width, self.height).unwrap();
+        if self.topology != GridTopology::default() {
+            writeln!(text, "topology = {}", self.topology.as_str()).unwrap();
+        }
+        writeln!(text, "countdown = {:?}", self.countdown_seconds).unwrap();
+        writeln!(text, "cell_seconds = {:?}", self.cell_seconds).unwrap();
+        writeln!(text, "fast_cell_seconds = {:?}", self.fast_cell_seconds).unwrap();
+        writeln!(text, "queue_seed = {}", self.queue_seed).unwrap();
+        writeln!(text, "queue_weights = {}", queue_weights_to_text(&self.queue_weights)).unwrap();
+        for start in &self.starts {
+            let p = start.position;
+            writeln!(text, "start = {},{} {}", p.x, p.y, start.exit.as_str()).unwrap();
+        }
+        for goal in &self.goals {
+            match self.goal_fills.iter().find(|(position, _)| position == goal) {
+                Some((_, volume)) => {
+                    writeln!(text, "goal = {},{} fill {:?}", goal.x, goal.y, volume).unwrap()
+                }
+                None => writeln!(text, "goal = {},{}", goal.x, goal.y).unwrap(),
+            }
+        }
+        if self.split_mode != SplitMode::default() {
+            writeln!(text, "split = {}", self.split_mode.as_str()).unwrap();
+        }
+        for tower in &self.towers {
+            writeln!(text, "tower = {},{}", tower.x, tower.y).unwrap();
+        }
+        for cell in &self.cells {
+            let p = cell.position;
+            let mut line = format!("cell = {},{} {}", p.x, p.y, cell.block.key.as_str());
+            line += &orientation_to_text(cell.block.orientation);
+            if cell.locked {
+                line += " locked";
+            }
+            writeln!(text, "{}", line).unwrap();
+        }
+        writeln!(text, "replace_allowed = {}", self.replacement.allowed).unwrap();
+        writeln!(text, "replace_delay = {:?}", self.replacement.delay_seconds).unwrap();
+        writeln!(text, "replace_penalty = {}", self.replacement.point_penalty).unwrap();
+        writeln!(text, "score_per_cell = {}", self.scoring.points_per_cell).unwrap();
+        writeln!(text, "score_cross_bonus = {}", self.scoring.cross_bonus).unwrap();
+        writeln!(text, "score_unused_penalty = {}", self.scoring.unused_piece_penalty).unwrap();
+        writeln!(text, "min_length = {}", self.scoring.min_length).unwrap();
+        if let Some(budget) = self.undo_budget {
+            writeln!(text, "undo_budget = {}", budget).unwrap();
+        }
+        text
+    }
+
+    // The streams of every start, towards the goals; None if the level has no start
+    pub fn flow_simulator(&self) -> Option<FlowSimulator> {
+        if self.starts.is_empty() {
+            return None;
+        }
+        let sources = self
+            .starts
+            .iter()
+            .map(|start| FlowHead {
+                position: start.position,
+                exit: start.exit,
+            })
+            .collect();
+        Some(
+            FlowSimulator::from_sources(sources)
+                .with_goals(self.goals.clone())
+                .with_goal_fills(&self.goal_fills)
+                .with_split_mode(self.split_mode)
+                .with_topology(self.topology),
+        )
+    }
+
+    pub fn clock_settings(&self) -> ClockSettings {
+        ClockSettings {
+            countdown_seconds: self.countdown_seconds,
+            seconds_per_cell: self.cell_seconds,
+            fast_seconds_per_cell: self.fast_cell_seconds,
+        }
+    }
+
+    // What the level has at 'position' (Void if nothing is pre-placed there)
+    pub fn block_at(&self, position: GridPosition) -> OrientedBlock {
+        if self.towers.contains(&position) {
+            return OrientedBlock::from(BlockKeys::Tower);
+        }
+        self.cells
+            .iter()
+            .find(|cell| cell.position == position)
+            .map(|cell| cell.block)
+            .unwrap_or(OrientedBlock::from(BlockKeys::Void))
+    }
+
+    // start/goal come from their own lists, the rest from what is pre-placed on the cell;
+    // pre-placed cells are replaceable unless they are locked
+    pub fn flags_at(&self, position: GridPosition) -> CellFlags {
+        let block = self.block_at(position);
+        let locked = self
+            .cells
+            .iter()
+            .any(|cell| cell.position == position && cell.locked);
+        CellFlags {
+            start: self.starts.iter().any(|start| start.position == position),
+            goal: self.goals.contains(&position),
+            locked,
+            replaceable: !locked,
+            ..CellFlags::pre_placed(block.key)
+        }
+    }
+
+    // Builds the [x][y] cell map; source ids are left at -1 since those are only known
+    // by the TileSet on the engine side
+    pub fn to_cell_map(&self, layer: LayerType) -> CellMap {
+        (0..self.width)
+            .map(|x| {
+                (0..self.height)
+                    .map(|y| {
+                        let position = GridPosition::new(x, y);
+                        let block = self.block_at(position);
+                        Some(BlockUnitCell {
+                            key: block.key,
+                            position,
+                            layer,
+                            cell_source_id: -1,
+                            orientation: block.orientation,
+                            flags: self.flags_at(position),
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Captures the non-void cells of a cell map as pre-placed cells (i.e. to save what a designer
+    // painted); everything else (starts, goals, queue, ...) is left as-is
+    pub fn set_cells_from(&mut self, cells: &CellMap, locked: bool) {
+        self.width = cells.len() as i32;
+        self.height = cells.first().map(|row| row.len()).unwrap_or(0) as i32;
+        self.cells = cells
+            .iter()
+            .flatten()
+            .flatten()
+            .filter(|cell| cell.key != BlockKeys::Void && cell.key != BlockKeys::Undefined)
+            // towers are kept in their own list, see block_at()
+            .filter(|cell| cell.key != BlockKeys::Tower)
+            .map(|cell| LevelCell {
+                position: cell.position,
+                block: OrientedBlock::new(cell.key, cell.orientation),
+                locked: locked || cell.flags.locked,
+            })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "
+        # example
+        version = 1
+        name = Tutorial 1
+        size = 8 x 6
+        topology = iso_diamond
+        countdown = 20.5
+        cell_seconds = 1.5
+        queue_seed = 1234
+        queue_weights = Router1Straight:3, Router1Corner:2
+        start = 0,2 East
+        start = 0,5 North
+        goal = 7,2
+        goal = 7,5 fill 2.0
+        split = divide
+        tower = 3,3
+        cell = 3,1 Router1Straight 90 locked
+        cell = 4,4 LineBlock4All
+        cell = 5,4 Router1Tee 270 flip
+        replace_penalty = 25
+        min_length = 12
+        undo_budget = 2
+    ";
+
+    #[test]
+    fn test_parse_and_round_trip() {
+        let level = Level::parse(EXAMPLE).unwrap();
+        assert_eq!(level.name, "Tutorial 1");
+        assert_eq!((level.width, level.height), (8, 6));
+        assert_eq!(level.topology, GridTopology::IsoDiamond);
+        assert_eq!(level.countdown_seconds, 20.5);
+        assert_eq!(level.clock_settings().seconds_per_cell, 1.5);
+        assert_eq!(level.queue_weights.len(), 2);
+        assert_eq!(level.replacement.point_penalty, 25);
+        assert!(level.replacement.allowed);
+        assert_eq!(level.scoring.min_length, 12);
+        assert_eq!(level.undo_budget, Some(2));
+        assert_eq!(level.starts[0].exit, Direction::East);
+        assert_eq!(level.goal_fills, vec![(GridPosition::new(7, 5), 2.0)]);
+        assert_eq!(level.split_mode, SplitMode::Divide);
+        assert_eq!(level.flow_simulator().unwrap().heads().len(), 2);
+        assert!(level.cells[0].locked);
+        assert_eq!(
+            level.block_at(GridPosition::new(5, 4)).orientation,
+            Orientation::new(Rotation::Deg270, true)
+        );
+        let again = Level::parse(&level.to_text()).unwrap();
+        assert_eq!(again, level);
+        let numbered = Level {
+            name: "Level #3".to_string(),
+            ..level.clone()
+        };
+        assert_eq!(Level::parse(&numbered.to_text()).unwrap().name, "Level #3");
+
+        let cells = level.to_cell_map(0);
+        assert_eq!(cells.len(), 8);
+        assert_eq!(cells[3][1].unwrap().key, BlockKeys::Router1Straight);
+        assert_eq!(cells[0][0].unwrap().key, BlockKeys::Void);
+        assert_eq!(cells[3][3].unwrap().key, BlockKeys::Tower);
+        assert!(cells[3][1].unwrap().flags.locked && !cells[3][1].unwrap().flags.replaceable);
+        assert!(cells[5][4].unwrap().flags.pre_placed && cells[5][4].unwrap().flags.replaceable);
+        assert!(cells[0][2].unwrap().flags.start);
+        assert!(cells[7][2].unwrap().flags.goal);
+        let mut from_map = level.clone();
+        from_map.set_cells_from(&cells, false);
+        assert_eq!(from_map.cells.len(), 3);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Level::parse("version = 1\nsize = 2 x 2\nsizzle = 3"),
+            Err(LevelError::Parse {
+                line: 3,
+                message: "unknown key 'sizzle'".to_string()
+            })
+        );
+        assert!(matches!(
+            Level::parse("size = 2 x 2"),
+            Err(LevelError::Invalid(_))
+        ));
+        assert!(matches!(
+            Level::parse("version = 1\nsize = 2 x 2\ngoal = 2,0"),
+            Err(LevelError::Invalid(_))
+        ));
+        assert!(matches!(
+            Level::parse("version = 1\nsize = 2 x 2\ncell = 0,0 Router 45"),
+            Err(LevelError::Parse { line: 3, .. })
+        ));
+        assert_eq!(
+            Level::parse("version = 1\nsize = 2 x 2\ngoal = 1,1 fill"),
+            Err(LevelError::Parse {
+                line: 3,
+                message: "goal fill is missing a volume".to_string()
+            })
+        );
+        assert_eq!(
+            Level::parse("version = 1\nsize = 2 x 2\ngoal = 1,1 fill 3 junk"),
+            Err(LevelError::Parse {
+                line: 3,
+                message: "goal: unexpected 'junk'".to_string()
+            })
+        );
+        assert_eq!(
+            Level::parse("version = 1\nsize = 2 x 2\nstart = 0,0 East junk"),
+            Err(LevelError::Parse {
+                line: 3,
+                message: "start: unexpected 'junk'".to_string()
+            })
+        );
+    }
+}
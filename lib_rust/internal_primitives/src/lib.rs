@@ -1,6 +1,27 @@
-use std::collections::HashMap;
+pub mod animation;
+pub mod clock;
+pub mod combat;
+pub mod connectivity;
+pub mod flow;
+pub mod generator;
+#[cfg(feature = "godot")]
+pub mod godot_convert;
+pub mod hint;
+pub mod history;
+pub mod level;
+pub mod orientation;
+pub mod placement;
+pub mod replay;
+pub mod rng;
+pub mod route;
+pub mod scoring;
+pub mod session;
+pub mod solver;
+pub mod tile_queue;
+pub mod topology;
+pub mod units;
 
-use godot::prelude::*;
+use orientation::Orientation;
 
 // This is a module/crate in which the structures are shared between other gdextension crates
 // but is NOT exposed to the Godot Engine.
@@ -8,6 +29,11 @@ use godot::prelude::*;
 // to Godot Engine (via extension-library) will have to use Dictionary (viariant).
 // Hence there will be a Converter trait of From/To (via .into()) for the shared structures
 // for conviniences.
+// NOTE: This is the ONE place the primitive types (BlockKeys, BlockUnitsMapType, BlockUnitCell,
+// etc) are defined; block_units and autoload_primitives only 'use' them.  Everything in here is
+// plain Rust (so it can be unit-tested, and used by the CLI tools, without Godot), and all the
+// conversions from/to Godot types (GString, Vector2i, PackedScene...) live in godot_convert.rs,
+// which is only compiled with the "godot" feature (the gdextension crates turn it on).
 
 // TileMap::get_tileset() returns Option<Gd<crate::engine::TileSet>>, meaning you can
 // only have at most 1 TileSet (or None) per TileMap.  And at the same time, we will
@@ -45,96 +71,208 @@ pub enum BlockKeys {
     Router,           // 1 in, 3 out
     RouteJoin2To1,    // 2 in, 1 out
     RouteJoin3To1,    // 3 in, 1 out
+    Tower,            // static tower (second game mode), occupies the cell and shoots at passing units
 }
-type CellIdType = i32; // this is the id of the cell (i.e. the type of block)
-type LayerType = i32;
-#[derive(Debug, Clone, Copy, PartialEq)]
-struct BlockUnitCell {
-    key: BlockKeys,
-    position: Vector2i, // we're using godot primitives
-    layer: LayerType,   // this is the layer of the cell
-    cell_source_id: CellIdType,
-}
-// as much as I appreciate Tuples, they are anonymous and are ref'ed by positon (i.e. tup.0, tup.1, and tup.2, etc)
-// so I'll stick with struct for my KVP values in case it grows fatter than 2 elements...
-#[derive(Debug, Clone, PartialEq)]
-struct BlockUnitCellKVPValue {
-    source_id: CellIdType,
-    scene: Option<Gd<PackedScene>>, 
-    resource_path: Option<GString>, // use GString here?
-}
-
-// e.g. let mut my_dict: BlockUnitCellDictionaryType<'static> = HashMap::new();  // Key: BlockKeys, Value: BlockUnitCellKVPValue
-type TBlockUnitCellDictionaryType = HashMap<BlockKeys, BlockUnitCellKVPValue>;
-#[derive(Debug, Clone, PartialEq)]
-struct BlockUnitCellDictionaryType {
-    dict: TBlockUnitCellDictionaryType, // HashMap can clone, but not copy
-}
-impl BlockUnitCellDictionaryType {
-    fn new() -> Self {
-        BlockUnitCellDictionaryType {
-            dict: TBlockUnitCellDictionaryType::new(),
+impl BlockKeys {
+    pub const ALL: [BlockKeys; 14] = [
+        BlockKeys::Undefined,
+        BlockKeys::Void,
+        BlockKeys::LineBlock1Edge,
+        BlockKeys::LineBlock2Corner,
+        BlockKeys::LineBlock3T,
+        BlockKeys::LineBlock4All,
+        BlockKeys::Router1Cross,
+        BlockKeys::Router1Straight,
+        BlockKeys::Router1Corner,
+        BlockKeys::Router1Tee,
+        BlockKeys::Router,
+        BlockKeys::RouteJoin2To1,
+        BlockKeys::RouteJoin3To1,
+        BlockKeys::Tower,
+    ];
+
+    // name as written in text files (level files, replays, etc), same as the enum variant
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BlockKeys::Undefined => "Undefined",
+            BlockKeys::Void => "Void",
+            BlockKeys::LineBlock1Edge => "LineBlock1Edge",
+            BlockKeys::LineBlock2Corner => "LineBlock2Corner",
+            BlockKeys::LineBlock3T => "LineBlock3T",
+            BlockKeys::LineBlock4All => "LineBlock4All",
+            BlockKeys::Router1Cross => "Router1Cross",
+            BlockKeys::Router1Straight => "Router1Straight",
+            BlockKeys::Router1Corner => "Router1Corner",
+            BlockKeys::Router1Tee => "Router1Tee",
+            BlockKeys::Router => "Router",
+            BlockKeys::RouteJoin2To1 => "RouteJoin2To1",
+            BlockKeys::RouteJoin3To1 => "RouteJoin3To1",
+            BlockKeys::Tower => "Tower",
         }
     }
-    fn insert(&mut self, key: BlockKeys, value: BlockUnitCellKVPValue) -> Option<BlockUnitCellKVPValue> {
-        self.dict.insert(key, value)
-    }
-    fn get(&self, key: &BlockKeys) -> Option<&BlockUnitCellKVPValue> {
-        self.dict.get(key)
+}
+impl std::str::FromStr for BlockKeys {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BlockKeys::ALL
+            .into_iter()
+            .find(|key| key.as_str() == s)
+            .ok_or(())
     }
-    fn remove(&mut self, key: &BlockKeys) -> Option<BlockUnitCellKVPValue> {
-        self.dict.remove(key)
+}
+// Engine-independent (x, y) position on the grid, so that the pure-Rust modules (connectivity,
+// simulation, etc) do not have to depend on Godot primitives; use .into() to go back and forth
+// from/to Vector2i
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Default)]
+pub struct GridPosition {
+    pub x: i32,
+    pub y: i32,
+}
+impl GridPosition {
+    pub fn new(x: i32, y: i32) -> Self {
+        GridPosition { x, y }
     }
-    fn len(&self) -> usize {
-        self.dict.len()
+}
+
+pub type CellIdType = i32; // this is the id of the cell (i.e. the type of block)
+pub type LayerType = i32;
+
+// What the cell IS (as opposed to what is placed on it); mostly set up by the level, and enforced
+// by the placement API (see placement.rs)
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct CellFlags {
+    pub start: bool,       // stream/units come out of here, never placeable
+    pub goal: bool,        // stream/units go in here, never placeable
+    pub locked: bool,      // nothing can be placed over it (i.e. part of the level design)
+    pub pre_placed: bool,  // placed by the level (or in the Editor) rather than by the player
+    pub replaceable: bool, // if occupied, can the player place over it?
+    pub obstacle: bool,    // LineBlock*, Tower, etc
+}
+impl Default for CellFlags {
+    // a plain, empty cell which the player can freely place on (and over)
+    fn default() -> Self {
+        CellFlags {
+            start: false,
+            goal: false,
+            locked: false,
+            pre_placed: false,
+            replaceable: true,
+            obstacle: false,
+        }
     }
-    fn is_empty(&self) -> bool {
-        self.dict.is_empty()
+}
+impl CellFlags {
+    // cell that already has 'key' on it from the start (i.e. painted in the Editor)
+    pub fn pre_placed(key: BlockKeys) -> Self {
+        CellFlags {
+            pre_placed: key != BlockKeys::Void && key != BlockKeys::Undefined,
+            obstacle: key.is_obstacle(),
+            ..CellFlags::default()
+        }
     }
-    fn clear(&mut self) {
-        self.dict.clear()
+}
+// Packed as bits for GDScript: 1=start, 2=goal, 4=locked, 8=pre_placed, 16=replaceable, 32=obstacle
+impl From<CellFlags> for i64 {
+    fn from(flags: CellFlags) -> Self {
+        [
+            flags.start,
+            flags.goal,
+            flags.locked,
+            flags.pre_placed,
+            flags.replaceable,
+            flags.obstacle,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |bits, (bit, set)| if *set { bits | (1 << bit) } else { bits })
     }
-    fn keys(&self) -> std::collections::hash_map::Keys<BlockKeys, BlockUnitCellKVPValue> {
-        self.dict.keys()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockUnitCell {
+    pub key: BlockKeys,
+    pub position: GridPosition,
+    pub layer: LayerType,   // this is the layer of the cell
+    pub cell_source_id: CellIdType,
+    pub orientation: Orientation, // rotation/mirroring of the placed tile, ports rotates along with it
+    pub flags: CellFlags,
+}
+impl BlockUnitCell {
+    // openings of this cell as it is placed (i.e. after rotation/mirroring)
+    pub fn ports(&self) -> connectivity::BlockPorts {
+        self.key.oriented_ports(self.orientation)
     }
-    fn values(&self) -> std::collections::hash_map::Values<BlockKeys, BlockUnitCellKVPValue> {
-        self.dict.values()
+
+    // Puts the block on the cell on behalf of the player (does NOT check whether it is allowed,
+    // see placement::check_placement() for that)
+    pub fn place(&mut self, block: orientation::OrientedBlock) {
+        self.key = block.key;
+        self.orientation = block.orientation;
+        self.flags.pre_placed = false;
+        self.flags.obstacle = block.key.is_obstacle();
     }
 }
+// the 2D array of cells (i.e. the map), indexed as [x][y] (same as ForBlockUnits::cell_map)
+// where None means the cell is not assigned in the TileMap
+pub type CellMap = Vec<Vec<Option<BlockUnitCell>>>;
 
-// To be able to render it on the Godot editor (i.e. Inspector), we need to have a way to convert
-// the enum to a Godot primitive type (i.e. GString)
-// Once From<T> is implemented (conversion traits), you can then do "into()"
-// i.e. let gstring: GString = TileMapType::Undefined.into();
-impl From<BlockUnitsMapType> for GString {
-    fn from(tile_map_type: BlockUnitsMapType) -> Self {
-        match tile_map_type {
-            BlockUnitsMapType::Undefined => "Undefined".into(),
-            BlockUnitsMapType::PlayfieldTileMap => "PlayfieldTileMap".into(),
-            BlockUnitsMapType::QueueTileMap => "QueueTileMap".into(),
-        }
+// returns None for out-of-bound (including negative) positions as well as unassigned cells
+pub fn cell_at(cells: &CellMap, position: GridPosition) -> Option<&BlockUnitCell> {
+    if position.x < 0 || position.y < 0 {
+        return None;
     }
+    cells
+        .get(position.x as usize)?
+        .get(position.y as usize)?
+        .as_ref()
 }
-impl From<BlockUnitsMapType> for i64 {
-    fn from(tile_map_type: BlockUnitsMapType) -> Self {
-        match tile_map_type {
-            BlockUnitsMapType::Undefined => BlockUnitsMapType::Undefined.into(),
-            BlockUnitsMapType::PlayfieldTileMap => BlockUnitsMapType::PlayfieldTileMap.into(),
-            BlockUnitsMapType::QueueTileMap => BlockUnitsMapType::QueueTileMap.into(),
-        }
+
+pub fn cell_at_mut(cells: &mut CellMap, position: GridPosition) -> Option<&mut BlockUnitCell> {
+    if position.x < 0 || position.y < 0 {
+        return None;
     }
+    cells
+        .get_mut(position.x as usize)?
+        .get_mut(position.y as usize)?
+        .as_mut()
 }
+#[cfg(test)]
+pub(crate) mod test_utils {
+    use super::*;
+    use crate::orientation::Rotation;
 
-impl TryFrom<GString> for BlockUnitsMapType {
-    type Error = ();
+    // width x height map of Void cells, with the listed (x, y, key, rotation) placed on top
+    pub fn make_map(width: i32, height: i32, placed: &[(i32, i32, BlockKeys, Rotation)]) -> CellMap {
+        (0..width)
+            .map(|x| {
+                (0..height)
+                    .map(|y| {
+                        let (key, rotation) = placed
+                            .iter()
+                            .find(|p| p.0 == x && p.1 == y)
+                            .map(|p| (p.2, p.3))
+                            .unwrap_or((BlockKeys::Void, Rotation::Deg0));
+                        Some(BlockUnitCell {
+                            key,
+                            position: GridPosition::new(x, y),
+                            layer: 0,
+                            cell_source_id: 0,
+                            orientation: Orientation::new(rotation, false),
+                            flags: CellFlags::pre_placed(key),
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
 
-    fn try_from(godot_string: GString) -> Result<Self, Self::Error> {
-        match godot_string.to_string().as_str() {
-            "Undefined" => Ok(BlockUnitsMapType::Undefined),
-            "PlayfieldTileMap" => Ok(BlockUnitsMapType::PlayfieldTileMap),
-            "QueueTileMap" => Ok(BlockUnitsMapType::QueueTileMap),
-            _ => Err(()),
-        }
+// Same "lost in translation" caveat as BlockUnitsMapType below, the i64 is the order of
+// declaration (which is also what GDScript sees when passed across)
+impl From<BlockUnitsMapType> for i64 {
+    fn from(tile_map_type: BlockUnitsMapType) -> Self {
+        tile_map_type as i64
     }
 }
 impl TryFrom<i64> for BlockUnitsMapType {
@@ -151,28 +289,80 @@ impl TryFrom<i64> for BlockUnitsMapType {
         }
     }
 }
+impl BlockUnitsMapType {
+    // name as shown on the Inspector (PROPERTY_HINT_ENUM), same as the enum variant
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BlockUnitsMapType::Undefined => "Undefined",
+            BlockUnitsMapType::PlayfieldTileMap => "PlayfieldTileMap",
+            BlockUnitsMapType::QueueTileMap => "QueueTileMap",
+        }
+    }
+}
+impl std::str::FromStr for BlockUnitsMapType {
+    type Err = ();
 
-// rather than erroing, will just return an empty collection if cannot convert
-// Hopefully, we can ASSUME that Godot Dictionary will always have UNIQUE keys 
-// (rust HashMap does upsert to prevent duplicates, hence we can assume at least
-// from Rust side, it will always be unique)
-impl From<Dictionary> for BlockUnitCellDictionaryType {
-    fn from(dict: Dictionary) -> Self {
-        let mut new_dict = BlockUnitCellDictionaryType::new();
-        for key in dict.keys_array() {
-            let key: BlockKeys = key.try_into().unwrap();
-            let value: BlockUnitCellKVPValue = dict.get(key).try_into().unwrap();
-            new_dict.insert(key, value);
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Undefined" => Ok(BlockUnitsMapType::Undefined),
+            "PlayfieldTileMap" => Ok(BlockUnitsMapType::PlayfieldTileMap),
+            "QueueTileMap" => Ok(BlockUnitsMapType::QueueTileMap),
+            _ => Err(()),
         }
-        new_dict
     }
 }
-impl From<BlockUnitCellDictionaryType> for Dictionary {
-    fn from(dict: BlockUnitCellDictionaryType) -> Self {
-        let mut new_dict = Dictionary::new();
-        for (key, value) in dict {
-            new_dict.insert(key.into(), value.into());
+
+// Same "lost in translation" caveat as BlockUnitsMapType, the i64 is the order of declaration
+// which MUST match the BLOCK_KEYS enum in autoload_globals_tileset.gd
+impl From<BlockKeys> for i64 {
+    fn from(key: BlockKeys) -> Self {
+        key as i64
+    }
+}
+impl TryFrom<i64> for BlockKeys {
+    type Error = ();
+
+    fn try_from(i: i64) -> Result<Self, Self::Error> {
+        match i {
+            0 => Ok(BlockKeys::Undefined),
+            1 => Ok(BlockKeys::Void),
+            2 => Ok(BlockKeys::LineBlock1Edge),
+            3 => Ok(BlockKeys::LineBlock2Corner),
+            4 => Ok(BlockKeys::LineBlock3T),
+            5 => Ok(BlockKeys::LineBlock4All),
+            6 => Ok(BlockKeys::Router1Cross),
+            7 => Ok(BlockKeys::Router1Straight),
+            8 => Ok(BlockKeys::Router1Corner),
+            9 => Ok(BlockKeys::Router1Tee),
+            10 => Ok(BlockKeys::Router),
+            11 => Ok(BlockKeys::RouteJoin2To1),
+            12 => Ok(BlockKeys::RouteJoin3To1),
+            13 => Ok(BlockKeys::Tower),
+            _ => Err(()),
         }
-        new_dict
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_int_round_trip() {
+        // used to recurse forever (From<BlockUnitsMapType> for i64 called itself)
+        for map_type in [
+            BlockUnitsMapType::Undefined,
+            BlockUnitsMapType::PlayfieldTileMap,
+            BlockUnitsMapType::QueueTileMap,
+        ] {
+            let i: i64 = map_type.into();
+            assert_eq!(BlockUnitsMapType::try_from(i), Ok(map_type));
+            assert_eq!(map_type.as_str().parse(), Ok(map_type));
+        }
+        for key in BlockKeys::ALL {
+            let i: i64 = key.into();
+            assert_eq!(BlockKeys::try_from(i), Ok(key));
+        }
+        assert!(BlockKeys::try_from(BlockKeys::ALL.len() as i64).is_err());
+    }
+}
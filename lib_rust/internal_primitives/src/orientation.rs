@@ -0,0 +1,220 @@
+use crate::connectivity::{BlockPorts, Direction};
+use crate::BlockKeys;
+
+// Every BlockKeys shape is authored in a single (canonical) orientation (see connectivity.rs),
+// placed tiles can be rotated by 90 degree steps (clockwise) and/or mirrored.
+// NOTE: Mirroring is always applied FIRST (flip East <-> West), and THEN rotated, so that
+// i.e. a flipped+90 is the same no matter which order the player pressed the buttons.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Default)]
+pub enum Rotation {
+    #[default]
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl Rotation {
+    pub fn quarter_turns(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_quarter_turns(turns: i32) -> Rotation {
+        match turns.rem_euclid(4) {
+            0 => Rotation::Deg0,
+            1 => Rotation::Deg90,
+            2 => Rotation::Deg180,
+            _ => Rotation::Deg270,
+        }
+    }
+
+    pub fn degrees(self) -> i32 {
+        self.quarter_turns() as i32 * 90
+    }
+}
+
+impl Direction {
+    pub fn rotated_cw(self, rotation: Rotation) -> Direction {
+        let index = self as i32 + rotation.quarter_turns() as i32;
+        Direction::ALL[index.rem_euclid(4) as usize]
+    }
+
+    // mirror along the vertical axis (East <-> West, North and South stays)
+    pub fn mirrored(self) -> Direction {
+        match self {
+            Direction::East => Direction::West,
+            Direction::West => Direction::East,
+            other => other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Default)]
+pub struct Orientation {
+    pub rotation: Rotation,
+    pub flipped: bool, // mirrored horizontally (applied before rotation)
+}
+
+impl Orientation {
+    pub fn new(rotation: Rotation, flipped: bool) -> Self {
+        Orientation { rotation, flipped }
+    }
+
+    // i.e. the "rotate before placing" action
+    pub fn rotated_cw(self) -> Self {
+        Orientation {
+            rotation: Rotation::from_quarter_turns(self.rotation.quarter_turns() as i32 + 1),
+            ..self
+        }
+    }
+
+    pub fn rotated_ccw(self) -> Self {
+        Orientation {
+            rotation: Rotation::from_quarter_turns(self.rotation.quarter_turns() as i32 - 1),
+            ..self
+        }
+    }
+
+    // NOTE: flipping an already rotated piece mirrors it on screen, hence the rotation has
+    // to be mirrored as well (90 becomes 270 and vice versa) to keep "flip first" rule above
+    pub fn flipped(self) -> Self {
+        Orientation {
+            rotation: Rotation::from_quarter_turns(-(self.rotation.quarter_turns() as i32)),
+            flipped: !self.flipped,
+        }
+    }
+
+    // Where does the canonical 'side' end up after this orientation is applied?
+    pub fn apply(&self, side: Direction) -> Direction {
+        let side = if self.flipped { side.mirrored() } else { side };
+        side.rotated_cw(self.rotation)
+    }
+
+    // For the engine side: the instanced scene tile has to be rotated/scaled the same way,
+    // since TileSetScenesCollectionSource cannot transform the tile (atlas is always (0, 0))
+    pub fn rotation_degrees(&self) -> i32 {
+        self.rotation.degrees()
+    }
+    pub fn scale_x(&self) -> i32 {
+        if self.flipped {
+            -1
+        } else {
+            1
+        }
+    }
+}
+
+impl BlockPorts {
+    pub fn oriented(&self, orientation: Orientation) -> BlockPorts {
+        let mut ret = BlockPorts::closed();
+        for side in Direction::ALL {
+            if let Some(flow) = self.port(side) {
+                ret = ret.with(orientation.apply(side), flow);
+            }
+        }
+        if self.is_crossing() {
+            ret = ret.with_crossing();
+        }
+        ret
+    }
+}
+
+impl BlockKeys {
+    pub fn oriented_ports(&self, orientation: Orientation) -> BlockPorts {
+        self.ports().oriented(orientation)
+    }
+}
+
+// What the queue hands out (and what gets placed), a block together with the way it is facing
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct OrientedBlock {
+    pub key: BlockKeys,
+    pub orientation: Orientation,
+}
+
+impl OrientedBlock {
+    pub fn new(key: BlockKeys, orientation: Orientation) -> Self {
+        OrientedBlock { key, orientation }
+    }
+    pub fn ports(&self) -> BlockPorts {
+        self.key.oriented_ports(self.orientation)
+    }
+}
+
+impl From<BlockKeys> for OrientedBlock {
+    fn from(key: BlockKeys) -> Self {
+        OrientedBlock::new(key, Orientation::default())
+    }
+}
+
+// Packed as an int for the Godot side: bits 0..1 are the quarter turns, bit 2 is flipped
+impl From<Orientation> for i64 {
+    fn from(orientation: Orientation) -> Self {
+        let flipped = if orientation.flipped { 4 } else { 0 };
+        orientation.rotation.quarter_turns() as i64 | flipped
+    }
+}
+impl TryFrom<i64> for Orientation {
+    type Error = ();
+
+    fn try_from(i: i64) -> Result<Self, Self::Error> {
+        if !(0..8).contains(&i) {
+            return Err(());
+        }
+        Ok(Orientation::new(
+            Rotation::from_quarter_turns((i & 3) as i32),
+            (i & 4) != 0,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotate_corner() {
+        // canonical corner is North <-> East
+        let corner = BlockKeys::Router1Corner;
+        let ports = corner.oriented_ports(Orientation::new(Rotation::Deg90, false));
+        assert_eq!(ports.open_sides(), vec![Direction::East, Direction::South]);
+        let ports = corner.oriented_ports(Orientation::new(Rotation::Deg270, false));
+        assert_eq!(ports.open_sides(), vec![Direction::North, Direction::West]);
+        // mirrored corner is North <-> West
+        let ports = corner.oriented_ports(Orientation::new(Rotation::Deg0, true));
+        assert_eq!(ports.open_sides(), vec![Direction::North, Direction::West]);
+    }
+
+    #[test]
+    fn test_rotate_keeps_flow() {
+        // Tee: in from South; rotated 90 it is in from West, out North and South
+        let ports = BlockKeys::Router1Tee.oriented_ports(Orientation::new(Rotation::Deg90, false));
+        assert_eq!(
+            ports.exits_for(Direction::West),
+            vec![Direction::North, Direction::South]
+        );
+        assert!(ports.exits_for(Direction::North).is_empty());
+    }
+
+    #[test]
+    fn test_full_turn_and_flip() {
+        let o = Orientation::default();
+        assert_eq!(o.rotated_cw().rotated_cw().rotated_cw().rotated_cw(), o);
+        assert_eq!(o.rotated_cw().rotated_ccw(), o);
+        assert_eq!(o.flipped().flipped(), o);
+        // flip after rotation mirrors what is on screen
+        let on_screen = o.rotated_cw().flipped();
+        for side in Direction::ALL {
+            assert_eq!(on_screen.apply(side), o.rotated_cw().apply(side).mirrored());
+        }
+    }
+
+    #[test]
+    fn test_i64_round_trip() {
+        for i in 0..8 {
+            let o: Orientation = i.try_into().unwrap();
+            assert_eq!(i64::from(o), i);
+        }
+        assert!(Orientation::try_from(8).is_err());
+    }
+}
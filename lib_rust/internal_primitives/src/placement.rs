@@ -0,0 +1,288 @@
+use crate::flow::FlowSimulator;
+use crate::{cell_at, BlockKeys, CellMap, GridPosition};
+
+// Whether the player may put a piece on a cell, based on the cell's flags (see CellFlags).
+// The README rule is that the player can place over "unoccupied and/or preoccupied" cells,
+// except for the static ones (start, goal, and whatever the level locked down).
+// On top of that, PlacementRules decides about REPLACING (Pipe Mania's "bomb" a placed tile):
+// never while the stream is in the cell, and it costs points and/or time (during which the
+// player cannot place anything).
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlacementError {
+    OutOfGrid,
+    StartCell,
+    GoalCell,
+    Locked,
+    Obstacle,
+    NotReplaceable, // occupied, and this cell does not allow placing over it
+    FlowInCell,     // stream already reached the cell, so the piece is there to stay
+    Cooldown,       // still paying the time penalty of the previous replacement
+}
+
+impl PlacementError {
+    // short reason, i.e. for GDScript to show/log
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlacementError::OutOfGrid => "out_of_grid",
+            PlacementError::StartCell => "start_cell",
+            PlacementError::GoalCell => "goal_cell",
+            PlacementError::Locked => "locked",
+            PlacementError::Obstacle => "obstacle",
+            PlacementError::NotReplaceable => "not_replaceable",
+            PlacementError::FlowInCell => "flow_in_cell",
+            PlacementError::Cooldown => "cooldown",
+        }
+    }
+}
+
+impl std::fmt::Display for PlacementError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// Ok if a piece can be placed at 'position' (regardless of which piece it is)
+pub fn check_placement(cells: &CellMap, position: GridPosition) -> Result<(), PlacementError> {
+    let cell = cell_at(cells, position).ok_or(PlacementError::OutOfGrid)?;
+    let flags = cell.flags;
+    if flags.start {
+        return Err(PlacementError::StartCell);
+    }
+    if flags.goal {
+        return Err(PlacementError::GoalCell);
+    }
+    if flags.locked {
+        return Err(PlacementError::Locked);
+    }
+    if flags.obstacle || cell.key.is_obstacle() {
+        return Err(PlacementError::Obstacle);
+    }
+    let occupied = cell.key != BlockKeys::Void && cell.key != BlockKeys::Undefined;
+    if occupied && !flags.replaceable {
+        return Err(PlacementError::NotReplaceable);
+    }
+    Ok(())
+}
+
+// Per-level tuning of replacements (see 'replace_*' keys in level.rs)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplacementRules {
+    pub allowed: bool,
+    pub delay_seconds: f32, // player cannot place anything for this long after a replacement
+    pub point_penalty: i32,
+}
+
+impl Default for ReplacementRules {
+    fn default() -> Self {
+        ReplacementRules {
+            allowed: true,
+            delay_seconds: 1.0,
+            point_penalty: 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlacementOutcome {
+    Placed, // on an empty cell, free of charge
+    Replaced {
+        old_key: BlockKeys,
+        delay_seconds: f32,
+        point_penalty: i32,
+    },
+}
+
+// Keeps track of the penalties (and the time left on the replacement delay); the caller first
+// evaluate()s, and if it decides to go ahead with the placement, record()s the outcome.
+// Time is only advanced via advance() (i.e. from _process(delta)), so it can be tested without
+// the engine.
+#[derive(Debug, Clone, Default)]
+pub struct PlacementRules {
+    rules: ReplacementRules,
+    cooldown_seconds: f32,
+    penalty_points: i32,
+    replaced_count: u32,
+}
+
+impl PlacementRules {
+    pub fn new(rules: ReplacementRules) -> Self {
+        PlacementRules {
+            rules,
+            ..Default::default()
+        }
+    }
+
+    pub fn rules(&self) -> &ReplacementRules {
+        &self.rules
+    }
+
+    pub fn advance(&mut self, delta_seconds: f32) {
+        self.cooldown_seconds = (self.cooldown_seconds - delta_seconds).max(0.0);
+    }
+
+    pub fn cooldown_seconds(&self) -> f32 {
+        self.cooldown_seconds
+    }
+
+    // sum of point penalties so far (positive number, to be subtracted from the score)
+    pub fn penalty_points(&self) -> i32 {
+        self.penalty_points
+    }
+
+    pub fn replaced_count(&self) -> u32 {
+        self.replaced_count
+    }
+
+    // What would happen if the player put a piece (or Void, when clearing) at position
+    pub fn evaluate(
+        &self,
+        cells: &CellMap,
+        position: GridPosition,
+        flow: Option<&FlowSimulator>,
+    ) -> Result<PlacementOutcome, PlacementError> {
+        check_placement(cells, position)?;
+        if self.cooldown_seconds > 0.0 {
+            return Err(PlacementError::Cooldown);
+        }
+        let old_key = cell_at(cells, position)
+            .map(|cell| cell.key)
+            .unwrap_or(BlockKeys::Void);
+        if old_key == BlockKeys::Void || old_key == BlockKeys::Undefined {
+            return Ok(PlacementOutcome::Placed);
+        }
+        if flow.map(|flow| flow.is_reached(position)).unwrap_or(false) {
+            return Err(PlacementError::FlowInCell);
+        }
+        if !self.rules.allowed {
+            return Err(PlacementError::NotReplaceable);
+        }
+        Ok(PlacementOutcome::Replaced {
+            old_key,
+            delay_seconds: self.rules.delay_seconds,
+            point_penalty: self.rules.point_penalty,
+        })
+    }
+
+    pub fn record(&mut self, outcome: &PlacementOutcome) {
+        if let PlacementOutcome::Replaced {
+            delay_seconds,
+            point_penalty,
+            ..
+        } = outcome
+        {
+            self.cooldown_seconds = *delay_seconds;
+            self.penalty_points += point_penalty;
+            self.replaced_count += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orientation::Rotation;
+    use crate::test_utils::make_map;
+
+    #[test]
+    fn test_check_placement() {
+        let mut cells = make_map(
+            3,
+            2,
+            &[
+                (1, 0, BlockKeys::Router1Straight, Rotation::Deg0),
+                (2, 0, BlockKeys::LineBlock4All, Rotation::Deg0),
+            ],
+        );
+        assert_eq!(check_placement(&cells, GridPosition::new(0, 0)), Ok(()));
+        // pre-placed but replaceable
+        assert_eq!(check_placement(&cells, GridPosition::new(1, 0)), Ok(()));
+        assert_eq!(
+            check_placement(&cells, GridPosition::new(2, 0)),
+            Err(PlacementError::Obstacle)
+        );
+        assert_eq!(
+            check_placement(&cells, GridPosition::new(3, 0)),
+            Err(PlacementError::OutOfGrid)
+        );
+
+        cells[0][1].as_mut().unwrap().flags.start = true;
+        cells[1][1].as_mut().unwrap().flags.locked = true;
+        cells[1][0].as_mut().unwrap().flags.replaceable = false;
+        assert_eq!(
+            check_placement(&cells, GridPosition::new(0, 1)),
+            Err(PlacementError::StartCell)
+        );
+        assert_eq!(
+            check_placement(&cells, GridPosition::new(1, 1)),
+            Err(PlacementError::Locked)
+        );
+        assert_eq!(
+            check_placement(&cells, GridPosition::new(1, 0)),
+            Err(PlacementError::NotReplaceable)
+        );
+    }
+
+    #[test]
+    fn test_replacement_rules() {
+        use crate::connectivity::Direction;
+
+        // S = =
+        let cells = make_map(
+            3,
+            1,
+            &[
+                (1, 0, BlockKeys::Router1Straight, Rotation::Deg90),
+                (2, 0, BlockKeys::Router1Straight, Rotation::Deg90),
+            ],
+        );
+        let mut rules = PlacementRules::new(ReplacementRules {
+            allowed: true,
+            delay_seconds: 2.0,
+            point_penalty: 10,
+        });
+        let mut flow = FlowSimulator::new(GridPosition::new(0, 0), Direction::East);
+        flow.tick(&cells);
+
+        // stream is in (1,0), but not yet in (2,0)
+        assert_eq!(
+            rules.evaluate(&cells, GridPosition::new(1, 0), Some(&flow)),
+            Err(PlacementError::FlowInCell)
+        );
+        let outcome = rules
+            .evaluate(&cells, GridPosition::new(2, 0), Some(&flow))
+            .unwrap();
+        assert_eq!(
+            outcome,
+            PlacementOutcome::Replaced {
+                old_key: BlockKeys::Router1Straight,
+                delay_seconds: 2.0,
+                point_penalty: 10
+            }
+        );
+        rules.record(&outcome);
+        assert_eq!(rules.penalty_points(), 10);
+
+        // cannot place anything until the delay is over
+        assert_eq!(
+            rules.evaluate(&cells, GridPosition::new(0, 0), None),
+            Err(PlacementError::Cooldown)
+        );
+        rules.advance(1.5);
+        assert!(rules.evaluate(&cells, GridPosition::new(0, 0), None).is_err());
+        rules.advance(0.5);
+        assert_eq!(
+            rules.evaluate(&cells, GridPosition::new(0, 0), None),
+            Ok(PlacementOutcome::Placed)
+        );
+
+        let rules = PlacementRules::new(ReplacementRules {
+            allowed: false,
+            ..ReplacementRules::default()
+        });
+        assert_eq!(
+            rules.evaluate(&cells, GridPosition::new(2, 0), None),
+            Err(PlacementError::NotReplaceable)
+        );
+    }
+}
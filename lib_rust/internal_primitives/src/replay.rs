@@ -0,0 +1,417 @@
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::level::{
+    orientation_to_text, parse_block_key, parse_orientation_token, parse_position,
+    parse_queue_weights, queue_weights_to_text, Level, LevelError,
+};
+use crate::orientation::{Orientation, OrientedBlock};
+use crate::session::{GameSession, SessionInput};
+use crate::tile_queue::{TileQueue, TileQueueWeights};
+
+// A recorded run: the queue it was played with (seed, size and weights), every input with the
+// step (see session.rs STEP_SECONDS) it happened at, and the level itself, so that a replay
+// file alone is enough to reproduce the exact same grid, stream and score (i.e. attached to a
+// bug report).  Same "key = value" style as level files, with the level appended as-is after
+// a "[level]" line:
+//
+//      # replay
+//      replay = 1
+//      queue_seed = 1234
+//      queue_size = 5
+//      queue_weights = Router1Straight:3, Router1Corner:2
+//      queue_oriented = false      # were the pieces handed out in random orientations?
+//      end_step = 2400             # steps the run lasted (playback runs at least this long)
+//      input = 130 place 3,2 90    # step, then one of:
+//      input = 212 set 4,2 Router1Corner 180 flip  #   place x,y [rotation] [flip]
+//      input = 300 undo            #   set x,y Block [rotation] [flip]
+//      input = 310 fast_forward    #   undo, redo, pause, resume, fast_forward
+//      [level]
+//      version = 1
+//      ...
+//
+// NOTE: Bump REPLAY_FORMAT_VERSION whenever the meaning of an input changes.
+
+pub const REPLAY_FORMAT_VERSION: u32 = 1;
+
+const LEVEL_SECTION: &str = "[level]";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayInput {
+    pub step: u64,
+    pub input: SessionInput,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub version: u32,
+    pub queue_seed: u64,
+    pub queue_size: usize,
+    pub queue_weights: TileQueueWeights,
+    pub queue_oriented: bool,
+    pub end_step: u64,
+    pub inputs: Vec<ReplayInput>, // in step order
+    pub level: Level,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplayError {
+    Io(String),
+    Parse { line: usize, message: String }, // line is 1-based
+    Level(LevelError),                      // the embedded level
+}
+
+impl std::fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplayError::Io(message) => write!(f, "I/O error: {}", message),
+            ReplayError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            ReplayError::Level(e) => write!(f, "level: {}", e),
+        }
+    }
+}
+
+fn input_to_text(input: &SessionInput) -> String {
+    match input {
+        SessionInput::Place {
+            position,
+            orientation,
+        } => format!(
+            "place {},{}{}",
+            position.x,
+            position.y,
+            orientation_to_text(*orientation)
+        ),
+        SessionInput::Set { position, block } => format!(
+            "set {},{} {}{}",
+            position.x,
+            position.y,
+            block.key.as_str(),
+            orientation_to_text(block.orientation)
+        ),
+        SessionInput::Undo => "undo".to_string(),
+        SessionInput::Redo => "redo".to_string(),
+        SessionInput::Pause => "pause".to_string(),
+        SessionInput::Resume => "resume".to_string(),
+        SessionInput::FastForward => "fast_forward".to_string(),
+    }
+}
+
+// "<step> <input...>"
+fn parse_input(text: &str) -> Result<ReplayInput, String> {
+    let mut tokens = text.split_whitespace();
+    let step = tokens.next().unwrap_or("");
+    let step = step
+        .parse()
+        .map_err(|_| format!("bad input step '{}'", step))?;
+    let parse_orientation = |tokens: &mut dyn Iterator<Item = &str>| {
+        let mut orientation = Orientation::default();
+        for token in tokens {
+            parse_orientation_token(&mut orientation, token)?;
+        }
+        Ok::<Orientation, String>(orientation)
+    };
+    let input = match tokens.next().unwrap_or("") {
+        "place" => SessionInput::Place {
+            position: parse_position(tokens.next().unwrap_or(""))?,
+            orientation: parse_orientation(&mut tokens)?,
+        },
+        "set" => {
+            let position = parse_position(tokens.next().unwrap_or(""))?;
+            let key = parse_block_key(tokens.next().ok_or("set is missing a block")?)?;
+            SessionInput::Set {
+                position,
+                block: OrientedBlock::new(key, parse_orientation(&mut tokens)?),
+            }
+        }
+        "undo" => SessionInput::Undo,
+        "redo" => SessionInput::Redo,
+        "pause" => SessionInput::Pause,
+        "resume" => SessionInput::Resume,
+        "fast_forward" => SessionInput::FastForward,
+        unknown => return Err(format!("unknown input '{}'", unknown)),
+    };
+    Ok(ReplayInput { step, input })
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Replay, ReplayError> {
+        let text = std::fs::read_to_string(path).map_err(|e| ReplayError::Io(e.to_string()))?;
+        Replay::parse(&text)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        std::fs::write(path, self.to_text()).map_err(|e| ReplayError::Io(e.to_string()))
+    }
+
+    pub fn parse(text: &str) -> Result<Replay, ReplayError> {
+        let (header, level_text) = text.split_once(LEVEL_SECTION).ok_or(ReplayError::Parse {
+            line: text.lines().count().max(1),
+            message: format!("missing '{}' section", LEVEL_SECTION),
+        })?;
+        let mut replay = Replay {
+            version: 0,
+            queue_seed: 0,
+            queue_size: 0,
+            queue_weights: TileQueue::default_weights(),
+            queue_oriented: false,
+            end_step: 0,
+            inputs: Vec::new(),
+            level: Level::default(),
+        };
+        for (index, raw_line) in header.lines().enumerate() {
+            let line = raw_line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            replay
+                .parse_line(line)
+                .map_err(|message| ReplayError::Parse {
+                    line: index + 1,
+                    message,
+                })?;
+        }
+        if replay.version == 0 {
+            return Err(ReplayError::Parse {
+                line: 1,
+                message: "missing 'replay' (version)".to_string(),
+            });
+        }
+        replay.inputs.sort_by_key(|input| input.step);
+        replay.level = Level::parse(level_text).map_err(ReplayError::Level)?;
+        Ok(replay)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("expected 'key = value' but got '{}'", line))?;
+        let value = value.trim();
+        match key.trim() {
+            "replay" => {
+                self.version = value.parse().map_err(|e| format!("bad version: {}", e))?;
+                if self.version == 0 || self.version > REPLAY_FORMAT_VERSION {
+                    return Err(format!(
+                        "unsupported version {} (newest supported is {})",
+                        self.version, REPLAY_FORMAT_VERSION
+                    ));
+                }
+            }
+            "queue_seed" => {
+                self.queue_seed = value
+                    .parse()
+                    .map_err(|e| format!("bad queue_seed: {}", e))?
+            }
+            "queue_size" => {
+                self.queue_size = value
+                    .parse()
+                    .map_err(|e| format!("bad queue_size: {}", e))?
+            }
+            "queue_weights" => self.queue_weights = parse_queue_weights(value)?,
+            "queue_oriented" => {
+                self.queue_oriented = value
+                    .parse()
+                    .map_err(|e| format!("bad queue_oriented: {}", e))?
+            }
+            "end_step" => {
+                self.end_step = value.parse().map_err(|e| format!("bad end_step: {}", e))?
+            }
+            "input" => self.inputs.push(parse_input(value)?),
+            unknown => return Err(format!("unknown key '{}'", unknown)),
+        }
+        Ok(())
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        // NOTE: writing into a String never fails, hence the unwraps
+        writeln!(text, "replay = {}", self.version).unwrap();
+        writeln!(text, "queue_seed = {}", self.queue_seed).unwrap();
+        writeln!(text, "queue_size = {}", self.queue_size).unwrap();
+        writeln!(
+            text,
+            "queue_weights = {}",
+            queue_weights_to_text(&self.queue_weights)
+        )
+        .unwrap();
+        writeln!(text, "queue_oriented = {}", self.queue_oriented).unwrap();
+        writeln!(text, "end_step = {}", self.end_step).unwrap();
+        for input in &self.inputs {
+            writeln!(
+                text,
+                "input = {} {}",
+                input.step,
+                input_to_text(&input.input)
+            )
+            .unwrap();
+        }
+        writeln!(text, "{}", LEVEL_SECTION).unwrap();
+        text += &self.level.to_text();
+        text
+    }
+
+    // fresh session (nothing played yet) with the recorded level and queue
+    pub fn new_session(&self) -> GameSession {
+        let weights = self.queue_weights.clone();
+        let queue = match self.queue_oriented {
+            true => TileQueue::new_oriented(self.queue_size, self.queue_seed, weights),
+            false => TileQueue::new(self.queue_size, self.queue_seed, weights),
+        };
+        GameSession::new(self.level.clone(), queue)
+    }
+
+    // Plays the whole replay back: every input at its step, then keeps stepping until
+    // end_step; returns the session in the state the recorded run ended in
+    pub fn play(&self) -> GameSession {
+        let mut session = self.new_session();
+        for input in &self.inputs {
+            while session.step() < input.step {
+                session.run_step();
+            }
+            session.apply(input.input);
+        }
+        while session.step() < self.end_step {
+            session.run_step();
+        }
+        session
+    }
+}
+
+// Collects the inputs while a run is being played (by a GameSession, or by ForBlockUnits)
+#[derive(Debug, Clone)]
+pub struct ReplayRecorder {
+    replay: Replay,
+}
+
+impl ReplayRecorder {
+    // 'queue' MUST be the queue as it was before anything was taken off of it (only its seed,
+    // size, weights and whether it is oriented are recorded)
+    pub fn new(level: Level, queue: &TileQueue) -> Self {
+        ReplayRecorder {
+            replay: Replay {
+                version: REPLAY_FORMAT_VERSION,
+                queue_seed: queue.seed(),
+                queue_size: queue.capacity(),
+                queue_weights: queue.weights().clone(),
+                queue_oriented: queue.is_random_orientation(),
+                end_step: 0,
+                inputs: Vec::new(),
+                level,
+            },
+        }
+    }
+
+    pub fn record(&mut self, step: u64, input: SessionInput) {
+        self.replay.inputs.push(ReplayInput { step, input });
+        self.replay.end_step = self.replay.end_step.max(step);
+    }
+
+    pub fn input_count(&self) -> usize {
+        self.replay.inputs.len()
+    }
+
+    // the replay so far, lasting until 'end_step'
+    pub fn to_replay(&self, end_step: u64) -> Replay {
+        let mut replay = self.replay.clone();
+        replay.end_step = replay.end_step.max(end_step);
+        replay
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connectivity::Direction;
+    use crate::level::LevelStart;
+    use crate::orientation::Rotation;
+    use crate::{BlockKeys, GridPosition};
+
+    #[test]
+    fn test_record_and_play_back() {
+        let level = Level {
+            version: 1,
+            width: 5,
+            height: 3,
+            countdown_seconds: 2.0,
+            cell_seconds: 0.25,
+            starts: vec![LevelStart {
+                position: GridPosition::new(0, 1),
+                exit: Direction::East,
+            }],
+            goals: vec![GridPosition::new(4, 1)],
+            ..Level::default()
+        };
+        let queue = TileQueue::new_oriented(
+            4,
+            77,
+            vec![
+                (BlockKeys::Router1Straight, 3),
+                (BlockKeys::Router1Corner, 1),
+            ],
+        );
+        let mut session = GameSession::new(level.clone(), queue.clone());
+        let mut recorder = ReplayRecorder::new(level, &queue);
+        let east_west = Orientation::new(Rotation::Deg90, false);
+        let inputs = [
+            (
+                10,
+                SessionInput::Place {
+                    position: GridPosition::new(1, 1),
+                    orientation: east_west,
+                },
+            ),
+            (
+                20,
+                SessionInput::Place {
+                    position: GridPosition::new(2, 1),
+                    orientation: east_west,
+                },
+            ),
+            (25, SessionInput::Undo),
+            (30, SessionInput::Redo),
+            (40, SessionInput::Pause),
+            (70, SessionInput::Resume),
+            (
+                80,
+                SessionInput::Set {
+                    position: GridPosition::new(3, 1),
+                    block: OrientedBlock::new(BlockKeys::Router1Straight, east_west),
+                },
+            ),
+            (90, SessionInput::FastForward),
+        ];
+        for (step, input) in inputs {
+            while session.step() < step {
+                session.run_step();
+            }
+            session.apply(input);
+            recorder.record(session.step(), input);
+        }
+        session.advance(5.0);
+        assert!(session.is_finished());
+
+        // through the file format and back
+        let replay = Replay::parse(&recorder.to_replay(session.step()).to_text()).unwrap();
+        assert_eq!(replay.inputs.len(), inputs.len());
+        let played = replay.play();
+        assert_eq!(played.cells(), session.cells());
+        assert_eq!(played.queue(), session.queue());
+        assert_eq!(played.summary(), session.summary());
+        assert_eq!(
+            played.flow().unwrap().filled_cells(),
+            session.flow().unwrap().filled_cells()
+        );
+    }
+
+    #[test]
+    fn test_bad_input() {
+        let text = "replay = 1\ninput = 5 jump 1,1\n[level]\nversion = 1\nsize = 2 x 2\n";
+        assert_eq!(
+            Replay::parse(text),
+            Err(ReplayError::Parse {
+                line: 2,
+                message: "unknown input 'jump'".to_string()
+            })
+        );
+    }
+}
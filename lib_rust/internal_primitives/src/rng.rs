@@ -0,0 +1,49 @@
+// Tiny deterministic PRNG (SplitMix64) so that anything seeded (tile queue, level generator,
+// replays) produces the exact same sequence on every platform and every build.
+// NOTE: Deliberately NOT using an external rand crate, since their algorithms (and hence the
+// sequences) are allowed to change between versions, which would break recorded replays and
+// shared "daily" seeds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        SeededRng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // uniform-ish value in [0, bound), bound MUST be non-zero
+    pub fn next_below(&mut self, bound: u64) -> u64 {
+        assert!(bound > 0, "SeededRng::next_below() - bound must be non-zero");
+        ((self.next_u64() as u128 * bound as u128) >> 64) as u64
+    }
+
+    pub fn next_bool(&mut self) -> bool {
+        self.next_u64() & 1 == 1
+    }
+
+    // Picks one of the values proportionally to its weight, None if all weights are 0
+    pub fn pick_weighted<T: Copy>(&mut self, weights: &[(T, u32)]) -> Option<T> {
+        let total: u64 = weights.iter().map(|(_, weight)| *weight as u64).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut roll = self.next_below(total);
+        for (value, weight) in weights {
+            if roll < *weight as u64 {
+                return Some(*value);
+            }
+            roll -= *weight as u64;
+        }
+        None // unreachable, roll is always < total
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::connectivity::Direction;
+use crate::topology::GridTopology;
+use crate::{cell_at, BlockKeys, CellMap, GridPosition};
+
+// Route solver for the tdcraft-like (warehouse/resource) game mode, in which units have to
+// travel FROM the startpoint (i.e. lumber mill) TO the endpoint (i.e. forest) and then BACK
+// to the startpoint to carry the resources home.
+// Both startpoint and endpoint are static buildings which are treated as open on all four
+// sides; everything in between has to be placed BlockKeys, honouring the one-way ports of
+// routers and joins (hence a Router1Tee that splits on the way out cannot be used to merge
+// on the way back, the player has to lay down a separate return lane).
+// Which cell is next to which goes by the level's GridTopology (see topology.rs).
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoundTrip {
+    pub outbound: Vec<GridPosition>, // startpoint ... endpoint (both inclusive)
+    pub inbound: Vec<GridPosition>,  // endpoint ... startpoint (both inclusive)
+}
+
+impl RoundTrip {
+    // the whole trip, with the endpoint only listed once
+    pub fn path(&self) -> Vec<GridPosition> {
+        let mut path = self.outbound.clone();
+        path.extend(self.inbound.iter().skip(1));
+        path
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteError {
+    // 'broken_at' is the cell the route stops connecting at, the last cell of the longest
+    // stretch that can be travelled from the leg's starting point (or that starting point
+    // itself if nothing is reachable at all), see find_path()
+    NoOutbound { broken_at: GridPosition },
+    NoInbound { broken_at: GridPosition },
+}
+
+pub fn find_round_trip(
+    cells: &CellMap,
+    topology: GridTopology,
+    start: GridPosition,
+    end: GridPosition,
+) -> Result<RoundTrip, RouteError> {
+    let outbound = find_path(cells, topology, start, end)
+        .map_err(|broken_at| RouteError::NoOutbound { broken_at })?;
+    let inbound = find_path(cells, topology, end, start)
+        .map_err(|broken_at| RouteError::NoInbound { broken_at })?;
+    Ok(RoundTrip { outbound, inbound })
+}
+
+fn accepts_from(cells: &CellMap, position: GridPosition, side: Direction) -> bool {
+    match cell_at(cells, position) {
+        Some(cell) if cell.key != BlockKeys::Void => {
+            matches!(cell.ports().port(side), Some(flow) if flow.accepts())
+        }
+        _ => false,
+    }
+}
+
+// Breadth-first (hence shortest) search of a single leg, returns the path (from and to
+// inclusive) or the cell where it breaks.
+// The route breaks at a cell that has a way out the next cell does not take (or no way out at
+// all); of those, the one furthest (in cells travelled) from 'from' is reported, since that is
+// as far as the player got, and a branch left open on the way (i.e. the other side of a
+// Router1Tee) is not where the route is broken, no matter how close it is to 'to'.
+// NOTE: Because which sides a unit leaves through depends on which side it came in from,
+// the search state is (position, entry side) rather than just the position, which also
+// lets a Router1Cross be travelled through twice (once per channel).
+pub fn find_path(
+    cells: &CellMap,
+    topology: GridTopology,
+    from: GridPosition,
+    to: GridPosition,
+) -> Result<Vec<GridPosition>, GridPosition> {
+    type State = (GridPosition, Direction);
+    let mut came_from: HashMap<State, Option<State>> = HashMap::new();
+    let mut queue: VecDeque<(State, usize)> = VecDeque::new(); // and cells travelled so far
+    let mut broken_at = (0, from);
+
+    // rebuilds the path walking back from the last state
+    let rebuild = |came_from: &HashMap<State, Option<State>>, last: Option<State>| {
+        let mut path = vec![to];
+        let mut current = last;
+        while let Some(state) = current {
+            path.push(state.0);
+            current = came_from[&state];
+        }
+        path.push(from);
+        path.reverse();
+        path
+    };
+
+    // the building at 'from' is open on all sides
+    for exit in Direction::ALL {
+        let next = topology.port_neighbour(from, exit);
+        if next == to {
+            return Ok(rebuild(&came_from, None));
+        }
+        let state = (next, exit.opposite());
+        if accepts_from(cells, next, exit.opposite()) && !came_from.contains_key(&state) {
+            came_from.insert(state, None);
+            queue.push_back((state, 1));
+        }
+    }
+
+    while let Some((state, travelled)) = queue.pop_front() {
+        let (position, entry) = state;
+        let ports = match cell_at(cells, position) {
+            Some(cell) => cell.ports(),
+            None => continue,
+        };
+        let exits = ports.exits_for(entry);
+        let mut refused = exits.is_empty();
+        for exit in exits {
+            let next = topology.port_neighbour(position, exit);
+            if next == to {
+                return Ok(rebuild(&came_from, Some(state)));
+            }
+            let next_state = (next, exit.opposite());
+            let accepted = accepts_from(cells, next, exit.opposite());
+            refused |= !accepted;
+            if accepted && !came_from.contains_key(&next_state) {
+                came_from.insert(next_state, Some(state));
+                queue.push_back((next_state, travelled + 1));
+            }
+        }
+        // breadth-first, so the first cell found at any distance wins
+        if refused && travelled > broken_at.0 {
+            broken_at = (travelled, position);
+        }
+    }
+    Err(broken_at.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orientation::Rotation;
+    use crate::test_utils::make_map;
+
+    #[test]
+    fn test_two_way_pipe() {
+        // S = = E
+        let cells = make_map(
+            4,
+            1,
+            &[
+                (1, 0, BlockKeys::Router1Straight, Rotation::Deg90),
+                (2, 0, BlockKeys::Router1Straight, Rotation::Deg90),
+            ],
+        );
+        let trip = find_round_trip(
+            &cells,
+            GridTopology::Square,
+            GridPosition::new(0, 0),
+            GridPosition::new(3, 0),
+        )
+        .unwrap();
+        assert_eq!(trip.outbound.len(), 4);
+        assert_eq!(trip.inbound.first(), Some(&GridPosition::new(3, 0)));
+        assert_eq!(trip.path().len(), 7);
+    }
+
+    #[test]
+    fn test_one_way_cannot_return() {
+        // Router1Tee rotated 90 accepts from West and emits North/South, so it can only be
+        // travelled through one way
+        let cells = make_map(
+            3,
+            2,
+            &[
+                (1, 0, BlockKeys::Router1Tee, Rotation::Deg90),
+                (1, 1, BlockKeys::Router1Corner, Rotation::Deg0), // North <-> East
+            ],
+        );
+        // outbound: S(0,0) -> tee(1,0) -> south -> corner(1,1) -> east -> E(2,1)
+        let start = GridPosition::new(0, 0);
+        let end = GridPosition::new(2, 1);
+        let outbound = find_path(&cells, GridTopology::Square, start, end).unwrap();
+        assert_eq!(
+            outbound,
+            vec![start, GridPosition::new(1, 0), GridPosition::new(1, 1), end]
+        );
+        // but the tee does not accept from the south, so no way back
+        assert_eq!(
+            find_round_trip(&cells, GridTopology::Square, start, end),
+            Err(RouteError::NoInbound {
+                broken_at: GridPosition::new(1, 1)
+            })
+        );
+    }
+
+    #[test]
+    fn test_broken_at_end_of_detour() {
+        // S(0,1) to E(4,1): the tee sends one branch up and along the top row, which stops
+        // at (2,0) closer to E, and the other down and around the bottom, which is the
+        // longer one and stops at (2,3), furthest from E
+        let cells = make_map(
+            5,
+            4,
+            &[
+                (1, 1, BlockKeys::Router1Tee, Rotation::Deg90), // in West, out North/South
+                (1, 0, BlockKeys::Router1Corner, Rotation::Deg90), // East <-> South
+                (2, 0, BlockKeys::Router1Straight, Rotation::Deg90),
+                (1, 2, BlockKeys::Router1Straight, Rotation::Deg0),
+                (1, 3, BlockKeys::Router1Corner, Rotation::Deg0), // North <-> East
+                (2, 3, BlockKeys::Router1Straight, Rotation::Deg90),
+            ],
+        );
+        assert_eq!(
+            find_path(
+                &cells,
+                GridTopology::Square,
+                GridPosition::new(0, 1),
+                GridPosition::new(4, 1)
+            ),
+            Err(GridPosition::new(2, 3))
+        );
+    }
+
+    #[test]
+    fn test_nothing_placed() {
+        let cells = make_map(3, 3, &[]);
+        assert_eq!(
+            find_round_trip(
+                &cells,
+                GridTopology::Square,
+                GridPosition::new(0, 0),
+                GridPosition::new(2, 2)
+            ),
+            Err(RouteError::NoOutbound {
+                broken_at: GridPosition::new(0, 0)
+            })
+        );
+    }
+}
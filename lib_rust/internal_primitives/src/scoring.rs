@@ -0,0 +1,201 @@
+use std::collections::HashSet;
+
+use crate::flow::{FlowEvent, FlowSimulator};
+use crate::placement::PlacementOutcome;
+use crate::{cell_at, BlockKeys, CellMap, GridPosition};
+
+// Pipe-Mania mode scoring, driven by what the flow simulator (and the placement rules) report:
+// * every cell the stream fills is worth points
+// * stream going through a Router1Cross the second time (the other channel) is a bonus
+// * every replacement (see placement.rs) costs points
+// * once the stream ends, every piece the player placed that the stream never reached costs points
+// * the level is won if the stream filled at least 'min_length' cells, and every goal that requires
+//   a volume (see flow.rs) got it
+// All of it is tunable per level (see 'score_*' and 'min_length' keys in level.rs).
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScoringRules {
+    pub points_per_cell: i32,
+    pub cross_bonus: i32, // per Router1Cross filled through both channels
+    pub unused_piece_penalty: i32,
+    pub min_length: u32, // filled cells needed to win (a crossing filled twice counts twice)
+}
+
+impl Default for ScoringRules {
+    fn default() -> Self {
+        ScoringRules {
+            points_per_cell: 50,
+            cross_bonus: 500,
+            unused_piece_penalty: 100,
+            min_length: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScoreSummary {
+    pub total: i32,
+    pub filled_length: u32,
+    pub cross_bonuses: u32,
+    pub replaced_pieces: u32,
+    pub unused_pieces: u32,
+    pub won: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ScoreKeeper {
+    rules: ScoringRules,
+    points: i32,
+    filled_length: u32,
+    crosses_entered: HashSet<GridPosition>,
+    cross_bonuses: u32,
+    replaced_pieces: u32,
+    unused_pieces: u32,
+    unfilled_goals: u32, // goals still short of the volume they require, once finished
+    finished: bool,
+}
+
+impl ScoreKeeper {
+    pub fn new(rules: ScoringRules) -> Self {
+        ScoreKeeper {
+            rules,
+            ..Default::default()
+        }
+    }
+
+    pub fn rules(&self) -> &ScoringRules {
+        &self.rules
+    }
+
+    // current score (can go negative)
+    pub fn points(&self) -> i32 {
+        self.points
+    }
+
+    // Feed EVERY event of FlowSimulator::tick() through here (the grid is needed to tell
+    // what kind of piece the stream entered)
+    pub fn on_flow_event(&mut self, event: &FlowEvent, cells: &CellMap) {
+        if let FlowEvent::Entered { position, .. } = event {
+            self.filled_length += 1;
+            self.points += self.rules.points_per_cell;
+            let is_cross = matches!(cell_at(cells, *position), Some(cell) if cell.key == BlockKeys::Router1Cross);
+            if is_cross && !self.crosses_entered.insert(*position) {
+                self.cross_bonuses += 1;
+                self.points += self.rules.cross_bonus;
+            }
+        }
+    }
+
+    pub fn on_placement(&mut self, outcome: &PlacementOutcome) {
+        if let PlacementOutcome::Replaced { point_penalty, .. } = outcome {
+            self.replaced_pieces += 1;
+            self.points -= point_penalty;
+        }
+    }
+
+    // Call once the stream has ended; charges for the unused pieces (only the ones the player
+    // placed, pre-placed pieces and obstacles are not the player's fault) and tells if it is a win;
+    // calling it again only hands back the same summary, the pieces are charged for once
+    pub fn finish(&mut self, cells: &CellMap, flow: &FlowSimulator) -> ScoreSummary {
+        if self.finished {
+            return self.summary();
+        }
+        self.finished = true;
+        self.unused_pieces = cells
+            .iter()
+            .flatten()
+            .flatten()
+            .filter(|cell| cell.key != BlockKeys::Void && cell.key != BlockKeys::Undefined)
+            .filter(|cell| !cell.flags.pre_placed && !cell.flags.obstacle)
+            .filter(|cell| !flow.is_reached(cell.position))
+            .count() as u32;
+        self.points -= self.unused_pieces as i32 * self.rules.unused_piece_penalty;
+        self.unfilled_goals = flow.unfilled_goals().len() as u32;
+        self.summary()
+    }
+
+    pub fn summary(&self) -> ScoreSummary {
+        ScoreSummary {
+            total: self.points,
+            filled_length: self.filled_length,
+            cross_bonuses: self.cross_bonuses,
+            replaced_pieces: self.replaced_pieces,
+            unused_pieces: self.unused_pieces,
+            won: self.filled_length >= self.rules.min_length && self.unfilled_goals == 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connectivity::Direction;
+    use crate::orientation::Rotation;
+    use crate::test_utils::make_map;
+
+    #[test]
+    fn test_score_loop_with_crossing() {
+        // same loop as flow.rs test_loop_through_crossing, plus an unused piece at (2,2)
+        let mut cells = make_map(
+            3,
+            3,
+            &[
+                (1, 1, BlockKeys::Router1Cross, Rotation::Deg0),
+                (2, 1, BlockKeys::Router1Corner, Rotation::Deg270),
+                (2, 0, BlockKeys::Router1Corner, Rotation::Deg180),
+                (1, 0, BlockKeys::Router1Corner, Rotation::Deg90),
+                (2, 2, BlockKeys::Router1Straight, Rotation::Deg0),
+            ],
+        );
+        // all of them were placed by the player
+        for cell in cells.iter_mut().flatten().flatten() {
+            cell.flags.pre_placed = false;
+        }
+        let rules = ScoringRules {
+            points_per_cell: 10,
+            cross_bonus: 100,
+            unused_piece_penalty: 7,
+            min_length: 5,
+        };
+        let mut score = ScoreKeeper::new(rules);
+        score.on_placement(&PlacementOutcome::Replaced {
+            old_key: BlockKeys::Router1Tee,
+            delay_seconds: 0.0,
+            point_penalty: 3,
+        });
+        score.on_placement(&PlacementOutcome::Placed);
+
+        let mut flow = FlowSimulator::new(GridPosition::new(0, 1), Direction::East);
+        for event in flow.run_to_end(&cells, 100) {
+            score.on_flow_event(&event, &cells);
+        }
+        let summary = score.finish(&cells, &flow);
+        assert_eq!(summary.filled_length, 5);
+        assert_eq!(summary.cross_bonuses, 1);
+        assert_eq!(summary.replaced_pieces, 1);
+        assert_eq!(summary.unused_pieces, 1);
+        assert_eq!(summary.total, 5 * 10 + 100 - 3 - 7);
+        assert!(summary.won);
+    }
+
+    #[test]
+    fn test_finish_twice_charges_once() {
+        // stream spills right away, the straight at (2,0) is never reached
+        let mut cells = make_map(3, 1, &[(2, 0, BlockKeys::Router1Straight, Rotation::Deg0)]);
+        cells[2][0].as_mut().unwrap().flags.pre_placed = false;
+        let rules = ScoringRules {
+            unused_piece_penalty: 7,
+            ..Default::default()
+        };
+        let mut score = ScoreKeeper::new(rules);
+        let mut flow = FlowSimulator::new(GridPosition::new(0, 0), Direction::East);
+        for event in flow.run_to_end(&cells, 100) {
+            score.on_flow_event(&event, &cells);
+        }
+        let summary = score.finish(&cells, &flow);
+        assert_eq!(summary.unused_pieces, 1);
+        assert_eq!(summary.total, -7);
+        assert_eq!(score.finish(&cells, &flow), summary);
+        assert_eq!(score.points(), -7);
+    }
+}
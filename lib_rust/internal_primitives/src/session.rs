@@ -0,0 +1,370 @@
+use crate::clock::GameClock;
+use crate::flow::{FlowEvent, FlowSimulator, FlowState};
+use crate::history::{PlacementCommand, PlacementHistory, UndoError};
+use crate::level::Level;
+use crate::orientation::{Orientation, OrientedBlock};
+use crate::placement::{PlacementError, PlacementOutcome, PlacementRules};
+use crate::scoring::{ScoreKeeper, ScoreSummary};
+use crate::tile_queue::TileQueue;
+use crate::{cell_at, cell_at_mut, CellMap, GridPosition};
+
+// One play-through of a level, without the engine: grid, queue, placement rules, score, clock,
+// stream and undo history, moved forward in FIXED steps of STEP_SECONDS.
+// Fixed steps are what make a run reproducible: given the same level, the same queue and the
+// same inputs at the same step numbers, every step sees the exact same delta, so the clock
+// ticks the stream at the exact same moments (float sums of the real frame deltas would not).
+// This is what ForBlockUnits runs the game with (turning the SessionEvents into signals), and
+// what replays (see replay.rs) and the offline tools drive, so that all of them play by the
+// exact same rules.
+
+pub const STEP_SECONDS: f32 = 1.0 / 60.0;
+
+// Everything the player can do during a run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SessionInput {
+    // the head of the queue, in the given orientation
+    Place {
+        position: GridPosition,
+        orientation: Orientation,
+    },
+    // any block, not from the queue (Void to clear the cell)
+    Set {
+        position: GridPosition,
+        block: OrientedBlock,
+    },
+    Undo,
+    Redo,
+    Pause,
+    Resume,
+    FastForward,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SessionEvent {
+    Placed {
+        position: GridPosition,
+        block: OrientedBlock,
+        outcome: PlacementOutcome,
+    },
+    Rejected {
+        position: GridPosition,
+        error: PlacementError,
+    },
+    Undone(GridPosition),
+    Redone(GridPosition),
+    UndoRejected(UndoError),
+    FlowStarted,
+    Flow(FlowEvent),
+    Finished(ScoreSummary),
+}
+
+#[derive(Debug, Clone)]
+pub struct GameSession {
+    level: Level,
+    cells: CellMap,
+    queue: TileQueue,
+    rules: PlacementRules,
+    score: ScoreKeeper,
+    clock: GameClock,
+    flow: Option<FlowSimulator>,
+    history: PlacementHistory,
+    step: u64,
+    leftover_seconds: f32, // of advance(), not yet enough for a whole step
+    summary: Option<ScoreSummary>,
+}
+
+impl GameSession {
+    // 'queue' is passed in (rather than built off the level) since the queue the player
+    // actually saw may have a different seed/size than the level suggests
+    pub fn new(level: Level, queue: TileQueue) -> Self {
+        let cells = level.to_cell_map(0);
+        GameSession::with_cells(level, cells, queue)
+    }
+
+    // Same as new(), but on a grid that did not come off of the level (i.e. a map painted in
+    // the Editor, holes and all), so the level only brings the rules; such a session cannot be
+    // replayed from the level
+    pub fn with_cells(level: Level, cells: CellMap, queue: TileQueue) -> Self {
+        GameSession {
+            cells,
+            queue,
+            rules: PlacementRules::new(level.replacement),
+            score: ScoreKeeper::new(level.scoring),
+            clock: GameClock::new(level.clock_settings()),
+            flow: None,
+            history: PlacementHistory::new(level.undo_budget),
+            step: 0,
+            leftover_seconds: 0.0,
+            summary: None,
+            level,
+        }
+    }
+
+    pub fn level(&self) -> &Level {
+        &self.level
+    }
+
+    pub fn cells(&self) -> &CellMap {
+        &self.cells
+    }
+
+    pub fn queue(&self) -> &TileQueue {
+        &self.queue
+    }
+
+    pub fn flow(&self) -> Option<&FlowSimulator> {
+        self.flow.as_ref()
+    }
+
+    pub fn rules(&self) -> &PlacementRules {
+        &self.rules
+    }
+
+    pub fn score(&self) -> &ScoreKeeper {
+        &self.score
+    }
+
+    pub fn clock(&self) -> &GameClock {
+        &self.clock
+    }
+
+    pub fn history(&self) -> &PlacementHistory {
+        &self.history
+    }
+
+    // number of fixed steps run so far
+    pub fn step(&self) -> u64 {
+        self.step
+    }
+
+    // final score, once the stream has ended
+    pub fn summary(&self) -> Option<ScoreSummary> {
+        self.summary
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.summary.is_some()
+    }
+
+    // What placing at 'position' would come to right now (same check as apply() makes), i.e.
+    // for hover/highlight before the player clicks
+    pub fn check_placement(
+        &self,
+        position: GridPosition,
+    ) -> Result<PlacementOutcome, PlacementError> {
+        self.rules
+            .evaluate(&self.cells, position, self.flow.as_ref())
+    }
+
+    pub fn apply(&mut self, input: SessionInput) -> Vec<SessionEvent> {
+        match input {
+            SessionInput::Place {
+                position,
+                orientation,
+            } => vec![self.place(position, None, orientation)],
+            SessionInput::Set { position, block } => {
+                vec![self.place(position, Some(block), block.orientation)]
+            }
+            SessionInput::Undo => vec![self.undo_redo(true)],
+            SessionInput::Redo => vec![self.undo_redo(false)],
+            SessionInput::Pause => {
+                self.clock.pause();
+                Vec::new()
+            }
+            SessionInput::Resume => {
+                self.clock.resume();
+                Vec::new()
+            }
+            SessionInput::FastForward => {
+                self.clock.fast_forward();
+                Vec::new()
+            }
+        }
+    }
+
+    // 'block' None takes the head of the queue
+    fn place(
+        &mut self,
+        position: GridPosition,
+        block: Option<OrientedBlock>,
+        orientation: Orientation,
+    ) -> SessionEvent {
+        let outcome = match self.check_placement(position) {
+            Ok(outcome) => outcome,
+            Err(error) => return SessionEvent::Rejected { position, error },
+        };
+        // evaluate() already made sure the cell is on the grid
+        let before = *cell_at(&self.cells, position).unwrap();
+        let (block, queue_before, queue_after) = match block {
+            Some(block) => (block, None, None),
+            None => {
+                let queue_before = self.queue.clone();
+                let head = self.queue.get_head();
+                let block = OrientedBlock::new(head.key, orientation);
+                (block, Some(queue_before), Some(self.queue.clone()))
+            }
+        };
+        let cell = cell_at_mut(&mut self.cells, position).unwrap();
+        cell.place(block);
+        let after = *cell;
+        self.rules.record(&outcome);
+        self.score.on_placement(&outcome);
+        self.history.record(PlacementCommand {
+            position,
+            before,
+            after,
+            queue_before,
+            queue_after,
+            outcome,
+        });
+        SessionEvent::Placed {
+            position,
+            block,
+            outcome,
+        }
+    }
+
+    fn undo_redo(&mut self, undo: bool) -> SessionEvent {
+        let result = match undo {
+            true => self
+                .history
+                .undo(&mut self.cells, Some(&mut self.queue), self.flow.as_ref()),
+            false => self
+                .history
+                .redo(&mut self.cells, Some(&mut self.queue), self.flow.as_ref()),
+        };
+        match (result, undo) {
+            (Ok(command), true) => SessionEvent::Undone(command.position),
+            (Ok(command), false) => SessionEvent::Redone(command.position),
+            (Err(e), _) => SessionEvent::UndoRejected(e),
+        }
+    }
+
+    // Runs as many whole steps as 'delta_seconds' (plus what was left over last time) covers
+    pub fn advance(&mut self, delta_seconds: f32) -> Vec<SessionEvent> {
+        self.leftover_seconds += delta_seconds;
+        let mut events = Vec::new();
+        while self.leftover_seconds >= STEP_SECONDS {
+            self.leftover_seconds -= STEP_SECONDS;
+            events.extend(self.run_step());
+        }
+        events
+    }
+
+    // One fixed step; the step counter moves even while paused (so that inputs recorded
+    // during a pause replay at the right moment), game time does not
+    pub fn run_step(&mut self) -> Vec<SessionEvent> {
+        self.step += 1;
+        let mut events = Vec::new();
+        if self.clock.is_paused() {
+            return events;
+        }
+        self.rules.advance(STEP_SECONDS);
+        let step = self.clock.advance(STEP_SECONDS);
+        if step.flow_started {
+            match self.level.flow_simulator() {
+                Some(flow) => {
+                    self.flow = Some(flow);
+                    events.push(SessionEvent::FlowStarted);
+                }
+                None => {
+                    self.clock.finish();
+                    return events;
+                }
+            }
+        }
+        for _ in 0..step.flow_ticks {
+            let flow = match self.flow.as_mut() {
+                Some(flow) => flow,
+                None => break,
+            };
+            for event in flow.tick(&self.cells) {
+                self.score.on_flow_event(&event, &self.cells);
+                events.push(SessionEvent::Flow(event));
+            }
+            if flow.state() == FlowState::Ended {
+                let summary = self.score.finish(&self.cells, flow);
+                self.summary = Some(summary);
+                self.clock.finish();
+                events.push(SessionEvent::Finished(summary));
+                break;
+            }
+        }
+        events
+    }
+
+    // Runs steps until the stream ends (or 'max_steps' more steps went by); i.e. for the
+    // offline tools, once all the placements are in
+    pub fn run_to_end(&mut self, max_steps: u64) -> Option<ScoreSummary> {
+        for _ in 0..max_steps {
+            if self.summary.is_some() {
+                break;
+            }
+            self.run_step();
+        }
+        self.summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connectivity::Direction;
+    use crate::level::LevelStart;
+    use crate::orientation::Rotation;
+    use crate::BlockKeys;
+
+    // S = = G, with a 1 second countdown and 0.5 second per cell
+    fn straight_level() -> Level {
+        Level {
+            version: 1,
+            width: 4,
+            height: 1,
+            countdown_seconds: 1.0,
+            cell_seconds: 0.5,
+            starts: vec![LevelStart {
+                position: GridPosition::new(0, 0),
+                exit: Direction::East,
+            }],
+            goals: vec![GridPosition::new(3, 0)],
+            scoring: crate::scoring::ScoringRules {
+                min_length: 2,
+                ..Default::default()
+            },
+            ..Level::default()
+        }
+    }
+
+    #[test]
+    fn test_place_from_queue_and_finish() {
+        let queue = TileQueue::new(3, 5, vec![(BlockKeys::Router1Straight, 1)]);
+        let mut session = GameSession::new(straight_level(), queue);
+        let east_west = Orientation::new(Rotation::Deg90, false);
+        for x in 1..3 {
+            let events = session.apply(SessionInput::Place {
+                position: GridPosition::new(x, 0),
+                orientation: east_west,
+            });
+            assert!(matches!(events[0], SessionEvent::Placed { .. }));
+        }
+        let rejected = session.apply(SessionInput::Place {
+            position: GridPosition::new(0, 0),
+            orientation: east_west,
+        });
+        assert_eq!(
+            rejected[0],
+            SessionEvent::Rejected {
+                position: GridPosition::new(0, 0),
+                error: PlacementError::StartCell
+            }
+        );
+
+        // countdown + 3 cells (2 pipes and the goal) = 2.5 seconds
+        let events = session.advance(3.0);
+        assert!(events.contains(&SessionEvent::FlowStarted));
+        let summary = session.summary().unwrap();
+        assert!(summary.won);
+        assert_eq!(summary.filled_length, 2);
+        assert!(session.step() >= 150);
+    }
+}
@@ -0,0 +1,468 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::connectivity::{BlockPorts, Direction};
+use crate::level::Level;
+use crate::orientation::{Orientation, OrientedBlock, Rotation};
+use crate::placement::{check_placement, PlacementError};
+use crate::replay::{Replay, ReplayRecorder};
+use crate::scoring::ScoreSummary;
+use crate::session::{GameSession, SessionEvent, SessionInput};
+use crate::tile_queue::TileQueue;
+use crate::{cell_at, cell_at_mut, BlockKeys, CellMap, GridPosition};
+
+// Offline solver for the Pipe-Mania mode: can the level be won with the pieces its queue seed
+// hands out, and what is the best score?
+// The search lays a single stream from the (first) start, one cell at a time:
+// * pre-placed pieces the stream runs into are taken as-is (it cannot go around them)
+// * on an empty cell it either stops (stream spills there), or places one of the upcoming
+//   queue pieces that has exactly ONE way out for the side the stream comes in from (so
+//   straights, corners, crossings and the joins, but never a split)
+// * a crossing can be gone through twice (once per channel)
+// Pieces may be placed in any order, but the queue hands them out in order, so every piece
+// ahead of the last one used has to be dumped somewhere off the path (costing the unused
+// piece penalty).  For each way out only the piece that comes off the queue the earliest is
+// tried (plus a crossing, for the bonus), which keeps the branching down to ~4.
+// The best few paths (by estimated score) are then played out for real through a GameSession,
+// one placement per step and then fast-forwarded, so that the reported score and placement
+// sequence are what the game itself would give.
+// NOTE: Replacing pre-placed pieces, splitting the stream and the timing of the placements
+// (the player is assumed to be done before the stream gets there) are not searched, and
+// neither are the streams of any other starts than the first (they do flow when played out,
+// so levels that need them, i.e. goals requiring more volume than one start has, only come
+// out as won if the first stream's path happens to work for them as well).
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SolverSettings {
+    pub max_pieces: usize, // how far into the queue to look
+    pub max_nodes: u64,    // search budget
+    pub verified: usize,   // how many of the best paths to play out through a GameSession
+}
+
+impl Default for SolverSettings {
+    fn default() -> Self {
+        SolverSettings {
+            max_pieces: 40,
+            max_nodes: 500_000,
+            verified: 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolverError {
+    NoStart,
+}
+
+impl std::fmt::Display for SolverError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SolverError::NoStart => write!(f, "level has no start"),
+        }
+    }
+}
+
+// A played-out placement sequence
+#[derive(Debug, Clone, PartialEq)]
+pub struct Solution {
+    pub inputs: Vec<SessionInput>, // in the order they were played, ends with FastForward
+    pub path: Vec<GridPosition>,   // cells the stream filled
+    pub summary: ScoreSummary,
+    pub replay: Replay,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SolveReport {
+    pub min_length: u32,
+    pub longest_path: u32, // longest path found by the search (before playing it out)
+    pub best: Option<Solution>,
+    pub nodes: u64,
+    pub complete: bool, // false if the search ran out of budget before trying everything
+}
+
+impl SolveReport {
+    pub fn is_solvable(&self) -> bool {
+        self.best
+            .as_ref()
+            .map(|best| best.summary.won)
+            .unwrap_or(false)
+    }
+}
+
+// piece planned on the path, with the queue index it comes from
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Planned {
+    position: GridPosition,
+    block: OrientedBlock,
+    queue_index: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Candidate {
+    estimate: i32,
+    planned: Vec<Planned>,
+    // cell the stream spills into at the end of the path, dumps must stay off of it
+    spill_target: Option<GridPosition>,
+}
+
+struct Search<'a> {
+    level: &'a Level,
+    settings: &'a SolverSettings,
+    cells: CellMap, // level cells plus what is planned so far
+    queue_indices: HashMap<BlockKeys, Vec<usize>>,
+    used: HashMap<BlockKeys, usize>,
+    channels: HashSet<(GridPosition, Option<bool>)>,
+    planned: Vec<Planned>,
+    length: u32,
+    crosses: u32,
+    nodes: u64,
+    complete: bool,
+    longest_path: u32,
+    candidates: Vec<Candidate>, // best first
+}
+
+const MAX_STEPS: u64 = 60 * 60 * 60;
+
+fn all_orientations() -> impl Iterator<Item = Orientation> {
+    (0..8).map(|index| Orientation::new(Rotation::from_quarter_turns(index % 4), index >= 4))
+}
+
+impl<'a> Search<'a> {
+    fn estimate(&self) -> i32 {
+        let rules = &self.level.scoring;
+        let pieces_needed = self
+            .planned
+            .iter()
+            .map(|planned| planned.queue_index + 1)
+            .max()
+            .unwrap_or(0);
+        let dumps = (pieces_needed - self.planned.len()) as i32;
+        self.length as i32 * rules.points_per_cell + self.crosses as i32 * rules.cross_bonus
+            - dumps * rules.unused_piece_penalty
+    }
+
+    // the stream ends here
+    fn consider(&mut self, spill_target: Option<GridPosition>) {
+        self.longest_path = self.longest_path.max(self.length);
+        let estimate = self.estimate();
+        let index = self
+            .candidates
+            .iter()
+            .position(|candidate| candidate.estimate < estimate)
+            .unwrap_or(self.candidates.len());
+        if index >= self.settings.verified {
+            return;
+        }
+        self.candidates.insert(
+            index,
+            Candidate {
+                estimate,
+                planned: self.planned.clone(),
+                spill_target,
+            },
+        );
+        self.candidates.truncate(self.settings.verified);
+    }
+
+    fn next_queue_index(&self, key: BlockKeys) -> Option<usize> {
+        let used = self.used.get(&key).copied().unwrap_or(0);
+        self.queue_indices.get(&key)?.get(used).copied()
+    }
+
+    // stream is about to leave 'position' through 'exit'
+    fn extend(&mut self, position: GridPosition, exit: Direction) {
+        self.nodes += 1;
+        if self.nodes > self.settings.max_nodes {
+            self.complete = false;
+            return;
+        }
+        let target = self.level.topology.port_neighbour(position, exit);
+        let entry = exit.opposite();
+        if self.level.goals.contains(&target) {
+            self.consider(None);
+            return;
+        }
+        let cell = match cell_at(&self.cells, target) {
+            Some(cell) => *cell,
+            None => {
+                self.consider(None);
+                return;
+            }
+        };
+        if cell.key == BlockKeys::Void || cell.key == BlockKeys::Undefined {
+            self.consider(Some(target));
+            if check_placement(&self.cells, target).is_ok() {
+                for block in self.choices(entry) {
+                    self.place_and_extend(target, entry, block);
+                }
+            }
+            return;
+        }
+        // something already there (pre-placed, or planned earlier on the path)
+        self.pass_through(target, entry, cell.ports());
+    }
+
+    // For each way out: the piece that comes off the queue the earliest, plus a crossing
+    fn choices(&self, entry: Direction) -> Vec<(OrientedBlock, usize)> {
+        let mut best: HashMap<(Direction, bool), (OrientedBlock, usize)> = HashMap::new();
+        for key in BlockKeys::ALL {
+            let queue_index = match self.next_queue_index(key) {
+                Some(queue_index) => queue_index,
+                None => continue,
+            };
+            for orientation in all_orientations() {
+                let block = OrientedBlock::new(key, orientation);
+                let exits = block.ports().exits_for(entry);
+                if exits.len() != 1 {
+                    continue;
+                }
+                let slot = (exits[0], key == BlockKeys::Router1Cross);
+                match best.get(&slot) {
+                    Some((_, existing)) if *existing <= queue_index => {}
+                    _ => {
+                        best.insert(slot, (block, queue_index));
+                    }
+                }
+            }
+        }
+        let mut choices: Vec<(OrientedBlock, usize)> = best.into_values().collect();
+        // HashMap order is random, keep the search (and its budget cut-off) deterministic
+        choices.sort_by_key(|(block, queue_index)| {
+            (*queue_index, block.key as i64, i64::from(block.orientation))
+        });
+        choices
+    }
+
+    fn place_and_extend(
+        &mut self,
+        target: GridPosition,
+        entry: Direction,
+        choice: (OrientedBlock, usize),
+    ) {
+        let (block, queue_index) = choice;
+        let previous = *cell_at(&self.cells, target).unwrap();
+        cell_at_mut(&mut self.cells, target).unwrap().place(block);
+        *self.used.entry(block.key).or_insert(0) += 1;
+        self.planned.push(Planned {
+            position: target,
+            block,
+            queue_index,
+        });
+        self.pass_through(target, entry, block.ports());
+        self.planned.pop();
+        *self.used.get_mut(&block.key).unwrap() -= 1;
+        *cell_at_mut(&mut self.cells, target).unwrap() = previous;
+    }
+
+    // same rules as FlowSimulator::tick(), for a single stream
+    fn pass_through(&mut self, target: GridPosition, entry: Direction, ports: BlockPorts) {
+        if !matches!(ports.port(entry), Some(flow) if flow.accepts()) {
+            self.consider(None);
+            return;
+        }
+        let channel = match ports.is_crossing() {
+            true => Some(matches!(entry, Direction::North | Direction::South)),
+            false => None,
+        };
+        if !self.channels.insert((target, channel)) {
+            self.consider(None);
+            return;
+        }
+        let second_pass = channel
+            .map(|channel| self.channels.contains(&(target, Some(!channel))))
+            .unwrap_or(false);
+        self.length += 1;
+        if second_pass {
+            self.crosses += 1;
+        }
+        let exits = ports.exits_for(entry);
+        match exits.len() {
+            1 => self.extend(target, exits[0]),
+            // dead end, or a (pre-placed) split the search does not follow; the stream
+            // really ends (or goes on) however GameSession says when played out
+            _ => self.consider(None),
+        }
+        if second_pass {
+            self.crosses -= 1;
+        }
+        self.length -= 1;
+        self.channels.remove(&(target, channel));
+    }
+}
+
+// Plays the candidate out through a GameSession; None if the game did not go along with it
+// (i.e. a dump had nowhere to go)
+fn play_out(level: &Level, queue: &TileQueue, candidate: &Candidate) -> Option<Solution> {
+    let pieces_needed = candidate
+        .planned
+        .iter()
+        .map(|planned| planned.queue_index + 1)
+        .max()
+        .unwrap_or(0);
+    let path: HashSet<GridPosition> = candidate.planned.iter().map(|p| p.position).collect();
+    let level_cells = level.to_cell_map(0);
+    let dump_cells: Vec<GridPosition> = level_cells
+        .iter()
+        .flatten()
+        .flatten()
+        .filter(|cell| cell.key == BlockKeys::Void)
+        .map(|cell| cell.position)
+        .filter(|position| check_placement(&level_cells, *position).is_ok())
+        .filter(|position| !path.contains(position) && Some(*position) != candidate.spill_target)
+        .collect();
+
+    let mut inputs = Vec::new();
+    let mut dumps = dump_cells.iter().cycle();
+    for queue_index in 0..pieces_needed {
+        let input = match candidate
+            .planned
+            .iter()
+            .find(|planned| planned.queue_index == queue_index)
+        {
+            Some(planned) => SessionInput::Place {
+                position: planned.position,
+                orientation: planned.block.orientation,
+            },
+            None => SessionInput::Place {
+                position: *dumps.next()?,
+                orientation: Orientation::default(),
+            },
+        };
+        inputs.push(input);
+    }
+    inputs.push(SessionInput::FastForward);
+
+    let mut session = GameSession::new(level.clone(), queue.clone());
+    let mut recorder = ReplayRecorder::new(level.clone(), queue);
+    for input in &inputs {
+        // wait out the delay of a replacement (when dumping on the same cell twice)
+        loop {
+            let events = session.apply(*input);
+            match events.first() {
+                Some(SessionEvent::Rejected {
+                    error: PlacementError::Cooldown,
+                    ..
+                }) if !session.is_finished() => {
+                    session.run_step();
+                }
+                Some(SessionEvent::Rejected { .. }) => return None,
+                _ => break,
+            }
+        }
+        recorder.record(session.step(), *input);
+        session.run_step();
+    }
+    let summary = session.run_to_end(MAX_STEPS)?;
+    Some(Solution {
+        inputs,
+        path: session.flow()?.filled_cells().to_vec(),
+        summary,
+        replay: recorder.to_replay(session.step()),
+    })
+}
+
+// Searches the level with the pieces 'queue' hands out (see the notes at the top)
+pub fn solve(
+    level: &Level,
+    queue: &TileQueue,
+    settings: &SolverSettings,
+) -> Result<SolveReport, SolverError> {
+    let start = *level.starts.first().ok_or(SolverError::NoStart)?;
+    let mut upcoming = queue.clone();
+    let mut queue_indices: HashMap<BlockKeys, Vec<usize>> = HashMap::new();
+    for queue_index in 0..settings.max_pieces {
+        let key = upcoming.get_head().key;
+        queue_indices.entry(key).or_default().push(queue_index);
+    }
+    let mut search = Search {
+        level,
+        settings,
+        cells: level.to_cell_map(0),
+        queue_indices,
+        used: HashMap::new(),
+        channels: HashSet::new(),
+        planned: Vec::new(),
+        length: 0,
+        crosses: 0,
+        nodes: 0,
+        complete: true,
+        longest_path: 0,
+        candidates: Vec::new(),
+    };
+    search.extend(start.position, start.exit);
+
+    let best = search
+        .candidates
+        .iter()
+        .filter_map(|candidate| play_out(level, queue, candidate))
+        .max_by_key(|solution| (solution.summary.won, solution.summary.total));
+    Ok(SolveReport {
+        min_length: level.scoring.min_length,
+        longest_path: search.longest_path,
+        best,
+        nodes: search.nodes,
+        complete: search.complete,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::{LevelCell, LevelStart};
+    use crate::scoring::ScoringRules;
+
+    // 5 x 3, start on the left, goal on the right, a locked wall in the middle of the row
+    fn level() -> Level {
+        Level {
+            version: 1,
+            width: 5,
+            height: 3,
+            countdown_seconds: 20.0,
+            starts: vec![LevelStart {
+                position: GridPosition::new(0, 1),
+                exit: Direction::East,
+            }],
+            goals: vec![GridPosition::new(4, 1)],
+            cells: vec![LevelCell {
+                position: GridPosition::new(2, 1),
+                block: OrientedBlock::from(BlockKeys::LineBlock4All),
+                locked: true,
+            }],
+            scoring: ScoringRules {
+                min_length: 5,
+                ..ScoringRules::default()
+            },
+            ..Level::default()
+        }
+    }
+
+    #[test]
+    fn test_solves_around_wall() {
+        let level = level();
+        let queue = TileQueue::new(
+            3,
+            11,
+            vec![
+                (BlockKeys::Router1Straight, 1),
+                (BlockKeys::Router1Corner, 1),
+            ],
+        );
+        let report = solve(&level, &queue, &SolverSettings::default()).unwrap();
+        assert!(report.complete);
+        assert!(report.is_solvable());
+        let best = report.best.unwrap();
+        // around the wall takes at least 5 cells
+        assert!(best.summary.filled_length >= 5);
+        // the replay reproduces the solution
+        assert_eq!(best.replay.play().summary(), Some(best.summary));
+    }
+
+    #[test]
+    fn test_unsolvable_with_straights_only() {
+        let level = level();
+        let queue = TileQueue::new(3, 11, vec![(BlockKeys::Router1Straight, 1)]);
+        let report = solve(&level, &queue, &SolverSettings::default()).unwrap();
+        assert!(!report.is_solvable());
+        // one straight, then the wall
+        assert_eq!(report.longest_path, 1);
+    }
+}
@@ -0,0 +1,178 @@
+use crate::orientation::{OrientedBlock, Orientation, Rotation};
+use crate::rng::SeededRng;
+use crate::BlockKeys;
+
+// Rust version of the "next tiles" queue (TileMap_NextTiles.gd), seeded so that the same
+// seed always hands out the same pieces in the same order.
+// Semantics are kept the same as the GDScript version:
+// * the queue is a FIXED size array (size of the QueueTileMap), head is index 0
+// * get_head() pops the head, shifts everything towards the head and refills the tail
+// * peek(count) returns up to 'count' pieces from the head without popping
+// * push_tail() adds a new random piece to the tail, popping the head if it is full
+
+// (BlockKeys, weight) pairs; the chance of a piece is weight / sum(weights)
+pub type TileQueueWeights = Vec<(BlockKeys, u32)>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileQueue {
+    slots: Vec<OrientedBlock>,
+    capacity: usize,
+    seed: u64,
+    weights: TileQueueWeights,
+    random_orientation: bool, // if false, pieces are always handed out in canonical orientation
+    rng: SeededRng,
+}
+
+impl TileQueue {
+    // same list (and same equal chance) as AllowedBlocks in TileMap_NextTiles.gd
+    pub fn default_weights() -> TileQueueWeights {
+        vec![
+            (BlockKeys::Router1Straight, 1),
+            (BlockKeys::Router1Corner, 1),
+            (BlockKeys::Router1Tee, 1),
+            (BlockKeys::Router, 1),
+            (BlockKeys::RouteJoin2To1, 1),
+            (BlockKeys::RouteJoin3To1, 1),
+            (BlockKeys::Router1Cross, 1),
+        ]
+    }
+
+    // Creates the queue and fills it up to capacity
+    pub fn new(capacity: usize, seed: u64, weights: TileQueueWeights) -> Self {
+        TileQueue::build(capacity, seed, weights, false)
+    }
+
+    // Same as new() but also randomizes the rotation of each piece
+    pub fn new_oriented(capacity: usize, seed: u64, weights: TileQueueWeights) -> Self {
+        TileQueue::build(capacity, seed, weights, true)
+    }
+
+    fn build(capacity: usize, seed: u64, weights: TileQueueWeights, random_orientation: bool) -> Self {
+        let mut queue = TileQueue {
+            slots: Vec::with_capacity(capacity),
+            capacity,
+            seed,
+            weights,
+            random_orientation,
+            rng: SeededRng::new(seed),
+        };
+        while queue.slots.len() < queue.capacity {
+            let next = queue.next_random();
+            queue.slots.push(next);
+        }
+        queue
+    }
+
+    fn next_random(&mut self) -> OrientedBlock {
+        // if nothing is allowed (all weights are zero), hand out void rather than panic
+        let key = self
+            .rng
+            .pick_weighted(&self.weights)
+            .unwrap_or(BlockKeys::Void);
+        let orientation = match self.random_orientation {
+            true => Orientation::new(
+                Rotation::from_quarter_turns(self.rng.next_below(4) as i32),
+                false,
+            ),
+            false => Orientation::default(),
+        };
+        OrientedBlock::new(key, orientation)
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // new_oriented() rather than new()
+    pub fn is_random_orientation(&self) -> bool {
+        self.random_orientation
+    }
+
+    pub fn weights(&self) -> &TileQueueWeights {
+        &self.weights
+    }
+
+    // Replaces the weights for pieces generated from now on (what is already queued stays)
+    pub fn set_weights(&mut self, weights: TileQueueWeights) {
+        self.weights = weights;
+    }
+
+    // pops the head and makes sure to populate the tail with new random piece
+    // (returns Void if the queue has zero capacity)
+    pub fn get_head(&mut self) -> OrientedBlock {
+        let head = self.pop_head();
+        self.push_tail();
+        head.unwrap_or(OrientedBlock::from(BlockKeys::Void))
+    }
+
+    // return up to 'count' pieces from the head without popping
+    pub fn peek(&self, count: usize) -> Vec<OrientedBlock> {
+        self.slots.iter().take(count).copied().collect()
+    }
+
+    // the whole queue, head first
+    pub fn as_slice(&self) -> &[OrientedBlock] {
+        &self.slots
+    }
+
+    pub fn pop_head(&mut self) -> Option<OrientedBlock> {
+        if self.slots.is_empty() {
+            return None;
+        }
+        Some(self.slots.remove(0))
+    }
+
+    // Push a random piece to the TAIL; if the queue is already full, the head is discarded
+    // to make space (same as push_cell_tail() in GDScript)
+    pub fn push_tail(&mut self) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.slots.len() >= self.capacity {
+            self.slots.remove(0);
+        }
+        let next = self.next_random();
+        self.slots.push(next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_queue() {
+        let mut a = TileQueue::new(3, 1234, TileQueue::default_weights());
+        let mut b = TileQueue::new(3, 1234, TileQueue::default_weights());
+        for _ in 0..50 {
+            assert_eq!(a.get_head(), b.get_head());
+        }
+        assert_eq!(a.peek(3), b.peek(3));
+    }
+
+    #[test]
+    fn test_get_head_shifts_and_refills() {
+        let mut queue = TileQueue::new(3, 42, TileQueue::default_weights());
+        let before = queue.peek(10);
+        assert_eq!(before.len(), 3);
+        let head = queue.get_head();
+        assert_eq!(head, before[0]);
+        assert_eq!(queue.peek(2), before[1..3].to_vec());
+        assert_eq!(queue.as_slice().len(), 3);
+    }
+
+    #[test]
+    fn test_weights() {
+        let queue = TileQueue::new(64, 7, vec![(BlockKeys::Router1Cross, 1), (BlockKeys::Router, 0)]);
+        assert!(queue
+            .as_slice()
+            .iter()
+            .all(|block| block.key == BlockKeys::Router1Cross));
+        let mut queue = TileQueue::new(2, 7, vec![]);
+        assert_eq!(queue.get_head().key, BlockKeys::Void);
+    }
+}
@@ -0,0 +1,318 @@
+use crate::connectivity::Direction;
+use crate::GridPosition;
+
+// How cells are laid out on the TileMap, so the same blocks (and the same ports, see
+// connectivity.rs), the same flow simulation, units and routes work on more than the square
+// grid:
+// * Square - what the game started with, N/E/S/W are up/right/down/left
+// * IsoDiamond - Godot's isometric TileMap (diamond down layout): same neighbours as Square,
+//   but the whole grid is turned 45 degrees, so North faces up-right, East down-right, etc
+// * HexOffset - pointy-top hexagons, odd rows shifted half a cell to the right (Godot's
+//   hexagon TileMap, stacked layout, horizontal offset axis)
+// * HexAxial - pointy-top hexagons in axial (q, r) coordinates (Godot's hexagon TileMap,
+//   stairs right layout, horizontal offset axis)
+// The names in brackets are how the TileSet (tile shape, tile layout and tile offset axis) has
+// to be set up in the Editor, ForBlockUnits::load_level() reports a TileSet that does not match.
+// A cell has 4 (GridSide) sides on the square layouts and 6 on the hexagons, see sides().
+// Blocks keep their four logical ports (North/East/South/West), port_side() decides which
+// side of the cell each of them goes through: on the hexagons, East/West are the right/left
+// sides, and North/South the up-right/down-left ones, so that opposite ports stay opposite
+// (straights stay straight), and corners turn by 60 degrees rather than 90.
+// NOTE: The up-left and down-right sides of a hexagon have no port, they are neighbours (see
+// neighbour()/side_between()) that the stream (or a unit) never passes through.
+// Anything that walks the stream from cell to cell should go through port_neighbour() and
+// port_between() rather than Direction::offset().
+
+// A side of a cell, only sides() of the topology are shared with a neighbour
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum GridSide {
+    North,
+    NorthEast,
+    East,
+    SouthEast,
+    South,
+    SouthWest,
+    West,
+    NorthWest,
+}
+
+impl GridSide {
+    pub fn opposite(self) -> GridSide {
+        match self {
+            GridSide::North => GridSide::South,
+            GridSide::NorthEast => GridSide::SouthWest,
+            GridSide::East => GridSide::West,
+            GridSide::SouthEast => GridSide::NorthWest,
+            GridSide::South => GridSide::North,
+            GridSide::SouthWest => GridSide::NorthEast,
+            GridSide::West => GridSide::East,
+            GridSide::NorthWest => GridSide::SouthEast,
+        }
+    }
+}
+
+const SQUARE_SIDES: [GridSide; 4] = [
+    GridSide::North,
+    GridSide::East,
+    GridSide::South,
+    GridSide::West,
+];
+const HEX_SIDES: [GridSide; 6] = [
+    GridSide::NorthEast,
+    GridSide::East,
+    GridSide::SouthEast,
+    GridSide::SouthWest,
+    GridSide::West,
+    GridSide::NorthWest,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GridTopology {
+    #[default]
+    Square,
+    IsoDiamond,
+    HexOffset,
+    HexAxial,
+}
+
+impl GridTopology {
+    pub const ALL: [GridTopology; 4] = [
+        GridTopology::Square,
+        GridTopology::IsoDiamond,
+        GridTopology::HexOffset,
+        GridTopology::HexAxial,
+    ];
+
+    // name as written in level files
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GridTopology::Square => "square",
+            GridTopology::IsoDiamond => "iso_diamond",
+            GridTopology::HexOffset => "hex_offset",
+            GridTopology::HexAxial => "hex_axial",
+        }
+    }
+
+    pub fn is_hex(&self) -> bool {
+        matches!(self, GridTopology::HexOffset | GridTopology::HexAxial)
+    }
+
+    // The sides a cell shares with its neighbours (clockwise)
+    pub fn sides(&self) -> &'static [GridSide] {
+        match self.is_hex() {
+            true => &HEX_SIDES,
+            false => &SQUARE_SIDES,
+        }
+    }
+
+    // (dx, dy) to get to the neighbour on 'side' of 'position' (on the offset hexagons, that
+    // depends on whether 'position' is on an odd or an even row); for a side that is not one
+    // of sides() (i.e. North of a hexagon) it is the plain step on the grid
+    pub fn offset(&self, position: GridPosition, side: GridSide) -> (i32, i32) {
+        let odd_row = position.y.rem_euclid(2) == 1;
+        match (self, side) {
+            (_, GridSide::North) => (0, -1),
+            (_, GridSide::East) => (1, 0),
+            (_, GridSide::South) => (0, 1),
+            (_, GridSide::West) => (-1, 0),
+            (GridTopology::HexAxial, GridSide::NorthEast) => (1, -1),
+            (GridTopology::HexAxial, GridSide::NorthWest) => (0, -1),
+            (GridTopology::HexAxial, GridSide::SouthEast) => (0, 1),
+            (GridTopology::HexAxial, GridSide::SouthWest) => (-1, 1),
+            (GridTopology::HexOffset, GridSide::NorthEast) => (if odd_row { 1 } else { 0 }, -1),
+            (GridTopology::HexOffset, GridSide::NorthWest) => (if odd_row { 0 } else { -1 }, -1),
+            (GridTopology::HexOffset, GridSide::SouthEast) => (if odd_row { 1 } else { 0 }, 1),
+            (GridTopology::HexOffset, GridSide::SouthWest) => (if odd_row { 0 } else { -1 }, 1),
+            (_, GridSide::NorthEast) => (1, -1),
+            (_, GridSide::NorthWest) => (-1, -1),
+            (_, GridSide::SouthEast) => (1, 1),
+            (_, GridSide::SouthWest) => (-1, 1),
+        }
+    }
+
+    pub fn neighbour(&self, position: GridPosition, side: GridSide) -> GridPosition {
+        let (dx, dy) = self.offset(position, side);
+        GridPosition::new(position.x + dx, position.y + dy)
+    }
+
+    // Which side of 'from' is 'to' on, None if the two are not neighbours
+    pub fn side_between(&self, from: GridPosition, to: GridPosition) -> Option<GridSide> {
+        self.sides()
+            .iter()
+            .copied()
+            .find(|side| self.neighbour(from, *side) == to)
+    }
+
+    // Side of the cell a block's (already oriented) port goes through
+    pub fn port_side(&self, port: Direction) -> GridSide {
+        match (self.is_hex(), port) {
+            (_, Direction::East) => GridSide::East,
+            (_, Direction::West) => GridSide::West,
+            (false, Direction::North) => GridSide::North,
+            (false, Direction::South) => GridSide::South,
+            (true, Direction::North) => GridSide::NorthEast,
+            (true, Direction::South) => GridSide::SouthWest,
+        }
+    }
+
+    // The port going through 'side', None if there is none (up-left/down-right of a hexagon)
+    pub fn side_port(&self, side: GridSide) -> Option<Direction> {
+        Direction::ALL
+            .into_iter()
+            .find(|port| self.port_side(*port) == side)
+    }
+
+    // The cell the stream goes to when it leaves 'position' through 'port'
+    pub fn port_neighbour(&self, position: GridPosition, port: Direction) -> GridPosition {
+        self.neighbour(position, self.port_side(port))
+    }
+
+    // Which port of 'from' leads to 'to', None if the two are not neighbours, or the side
+    // they share has no port
+    pub fn port_between(&self, from: GridPosition, to: GridPosition) -> Option<Direction> {
+        self.side_between(from, to)
+            .and_then(|side| self.side_port(side))
+    }
+
+    // Number of steps (between neighbours) from 'a' to 'b', not counting which sides have
+    // ports, so the stream may need more
+    pub fn distance(&self, a: GridPosition, b: GridPosition) -> i32 {
+        // offset rows to axial columns, so that both hexagon layouts can share the formula
+        let axial = |p: GridPosition| match self {
+            GridTopology::HexOffset => p.x - (p.y - p.y.rem_euclid(2)) / 2,
+            _ => p.x,
+        };
+        let (dx, dy) = (axial(b) - axial(a), b.y - a.y);
+        match self.is_hex() {
+            true => (dx.abs() + dy.abs() + (dx + dy).abs()) / 2,
+            false => dx.abs() + dy.abs(),
+        }
+    }
+
+    // Way the side faces on screen, in degrees clockwise from up (i.e. for rotating a scene
+    // tile, or pointing an animation)
+    pub fn side_angle_degrees(&self, side: GridSide) -> i32 {
+        let square = match side {
+            GridSide::North => 0,
+            GridSide::NorthEast => 45,
+            GridSide::East => 90,
+            GridSide::SouthEast => 135,
+            GridSide::South => 180,
+            GridSide::SouthWest => 225,
+            GridSide::West => 270,
+            GridSide::NorthWest => 315,
+        };
+        match (self, side) {
+            (GridTopology::Square, _) => square,
+            (GridTopology::IsoDiamond, _) => (square + 45) % 360,
+            (_, GridSide::NorthEast) => 30,
+            (_, GridSide::SouthEast) => 150,
+            (_, GridSide::SouthWest) => 210,
+            (_, GridSide::NorthWest) => 330,
+            (_, _) => square,
+        }
+    }
+}
+
+impl std::str::FromStr for GridTopology {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        GridTopology::ALL
+            .into_iter()
+            .find(|topology| topology.as_str() == s)
+            .ok_or(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_neighbours_are_mutual() {
+        // going out through a side and back through the opposite one ends up where it started,
+        // on every layout and on both odd and even rows
+        for topology in GridTopology::ALL {
+            for position in [GridPosition::new(3, 4), GridPosition::new(3, 5)] {
+                for side in topology.sides().iter().copied() {
+                    let neighbour = topology.neighbour(position, side);
+                    assert_ne!(neighbour, position);
+                    assert_eq!(topology.neighbour(neighbour, side.opposite()), position);
+                    assert_eq!(topology.side_between(position, neighbour), Some(side));
+                    assert_eq!(topology.distance(position, neighbour), 1);
+                    let angle = topology.side_angle_degrees(side);
+                    assert_eq!(
+                        (angle + 180) % 360,
+                        topology.side_angle_degrees(side.opposite())
+                    );
+                }
+                // and opposite ports go through opposite sides
+                for port in Direction::ALL {
+                    let side = topology.port_side(port);
+                    assert_eq!(topology.port_side(port.opposite()), side.opposite());
+                    assert_eq!(topology.side_port(side), Some(port));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_hex_offset_neighbours() {
+        let hex = GridTopology::HexOffset;
+        let neighbours = |x, y| {
+            hex.sides()
+                .iter()
+                .map(|side| hex.neighbour(GridPosition::new(x, y), *side))
+                .map(|p| (p.x, p.y))
+                .collect::<Vec<_>>()
+        };
+        // clockwise from up-right; even rows lean left, odd rows right
+        assert_eq!(
+            neighbours(2, 2),
+            vec![(2, 1), (3, 2), (2, 3), (1, 3), (1, 2), (1, 1)]
+        );
+        assert_eq!(
+            neighbours(2, 1),
+            vec![(3, 0), (3, 1), (3, 2), (2, 2), (1, 1), (2, 0)]
+        );
+        // up-left is a neighbour, but has no port
+        let up_left = GridPosition::new(1, 1);
+        assert_eq!(
+            hex.side_between(GridPosition::new(2, 2), up_left),
+            Some(GridSide::NorthWest)
+        );
+        assert_eq!(hex.port_between(GridPosition::new(2, 2), up_left), None);
+        assert_eq!(
+            hex.distance(GridPosition::new(0, 0), GridPosition::new(3, 4)),
+            5
+        );
+    }
+
+    #[test]
+    fn test_hex_axial_neighbours() {
+        let hex = GridTopology::HexAxial;
+        let origin = GridPosition::new(2, 2);
+        let neighbours: Vec<(i32, i32)> = hex
+            .sides()
+            .iter()
+            .map(|side| hex.neighbour(origin, *side))
+            .map(|p| (p.x, p.y))
+            .collect();
+        assert_eq!(
+            neighbours,
+            vec![(3, 1), (3, 2), (2, 3), (1, 3), (1, 2), (2, 1)]
+        );
+        assert_eq!(
+            hex.port_neighbour(origin, Direction::North),
+            GridPosition::new(3, 1)
+        );
+        assert_eq!(
+            hex.port_between(origin, GridPosition::new(1, 3)),
+            Some(Direction::South)
+        );
+        assert_eq!(hex.port_between(origin, GridPosition::new(2, 3)), None);
+        assert_eq!(hex.distance(origin, GridPosition::new(4, 0)), 2);
+        assert_eq!(hex.distance(origin, GridPosition::new(4, 4)), 4);
+    }
+}
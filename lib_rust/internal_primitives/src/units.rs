@@ -0,0 +1,441 @@
+use crate::connectivity::Direction;
+use crate::topology::GridTopology;
+use crate::{cell_at, BlockKeys, CellMap, GridPosition};
+
+// Goblets (units) that travel along the placed pipes/roads, from a start building towards
+// the goal building(s), i.e. the "goblets of different levels where some move faster, some
+// explode" from the README.
+// Same as FlowSimulator, this is a headless, deterministic per-tick update: the caller
+// decides how often tick() gets called, and passes the (current) grid on each tick since the
+// player keeps placing tiles while units are on the move.
+// Unlike the flow (goo), units do not fill the pipes, so they can travel over the same cell
+// over and over again (including loops), and when a router gives them more than one way out,
+// each unit picks deterministically based on its id so that a group spreads across the branches.
+// Which cell is next to which goes by the GridTopology (square by default, see topology.rs).
+
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum GobletKind {
+    Grunt,  // the plain one
+    Runner, // fast but fragile, carries little
+    Hauler, // slow and tough, carries a lot
+    Bomber, // blows up (taking itself out) when it runs into a dead end
+    Bot,    // fires back at towers in range (see combat.rs)
+}
+
+// Only bots have these; towers in range get shot at every 'fire_interval_ticks'
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CounterAttack {
+    pub range: i32, // in cells (euclidean distance)
+    pub damage: i32,
+    pub fire_interval_ticks: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GobletStats {
+    pub ticks_per_cell: u32, // how many ticks it takes to move one cell (lower is faster, min 1)
+    pub max_hp: i32,
+    pub payload: u32, // delivered when it reaches a goal
+    pub explode_on_dead_end: bool,
+    pub counter_attack: Option<CounterAttack>,
+}
+
+impl GobletKind {
+    pub const ALL: [GobletKind; 5] = [
+        GobletKind::Grunt,
+        GobletKind::Runner,
+        GobletKind::Hauler,
+        GobletKind::Bomber,
+        GobletKind::Bot,
+    ];
+
+    // default stats per kind; levels (or anything else) can override by spawning with custom stats
+    pub fn stats(&self) -> GobletStats {
+        match self {
+            GobletKind::Grunt => GobletStats {
+                ticks_per_cell: 2,
+                max_hp: 10,
+                payload: 2,
+                explode_on_dead_end: false,
+                counter_attack: None,
+            },
+            GobletKind::Runner => GobletStats {
+                ticks_per_cell: 1,
+                max_hp: 5,
+                payload: 1,
+                explode_on_dead_end: false,
+                counter_attack: None,
+            },
+            GobletKind::Hauler => GobletStats {
+                ticks_per_cell: 4,
+                max_hp: 25,
+                payload: 5,
+                explode_on_dead_end: false,
+                counter_attack: None,
+            },
+            GobletKind::Bomber => GobletStats {
+                ticks_per_cell: 2,
+                max_hp: 8,
+                payload: 0,
+                explode_on_dead_end: true,
+                counter_attack: None,
+            },
+            GobletKind::Bot => GobletStats {
+                ticks_per_cell: 3,
+                max_hp: 15,
+                payload: 1,
+                explode_on_dead_end: false,
+                counter_attack: Some(CounterAttack {
+                    range: 2,
+                    damage: 5,
+                    fire_interval_ticks: 2,
+                }),
+            },
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GobletKind::Grunt => "Grunt",
+            GobletKind::Runner => "Runner",
+            GobletKind::Hauler => "Hauler",
+            GobletKind::Bomber => "Bomber",
+            GobletKind::Bot => "Bot",
+        }
+    }
+}
+
+impl std::str::FromStr for GobletKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        GobletKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or(())
+    }
+}
+
+pub type GobletId = u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GobletState {
+    Moving,
+    Stuck,    // nowhere to go (yet), will keep trying each tick in case the player fixes the path
+    Arrived,  // made it into a goal, payload delivered
+    Exploded, // ran into a dead end (explode_on_dead_end units only)
+    Killed,   // hp went down to 0 (i.e. shot by a tower)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Goblet {
+    pub id: GobletId,
+    pub kind: GobletKind,
+    pub stats: GobletStats,
+    pub hp: i32,
+    pub position: GridPosition,
+    // side it came in from, None while still in the start building (which only has 'spawn_exit')
+    pub entry: Option<Direction>,
+    spawn_exit: Direction,
+    progress: u32, // ticks spent in the current cell
+    pub state: GobletState,
+}
+
+impl Goblet {
+    // still on the grid (can move, be shot at, etc)
+    pub fn is_active(&self) -> bool {
+        matches!(self.state, GobletState::Moving | GobletState::Stuck)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GobletEvent {
+    Moved {
+        id: GobletId,
+        from: GridPosition,
+        to: GridPosition,
+    },
+    // only reported when it becomes stuck, not on every tick it stays stuck
+    Stuck {
+        id: GobletId,
+        position: GridPosition,
+    },
+    Arrived {
+        id: GobletId,
+        goal: GridPosition,
+        payload: u32,
+    },
+    Exploded {
+        id: GobletId,
+        position: GridPosition,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GobletSimulator {
+    goblets: Vec<Goblet>,
+    goals: Vec<GridPosition>, // buildings, open on all sides
+    topology: GridTopology,
+    next_id: GobletId,
+    delivered_payload: u32,
+    tick_count: u64,
+}
+
+impl GobletSimulator {
+    pub fn new(goals: Vec<GridPosition>) -> Self {
+        GobletSimulator {
+            goals,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_topology(mut self, topology: GridTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn topology(&self) -> GridTopology {
+        self.topology
+    }
+
+    // Spawns a goblet (with default stats of its kind) in the start building at 'position',
+    // leaving it through 'exit'
+    pub fn spawn(&mut self, kind: GobletKind, position: GridPosition, exit: Direction) -> GobletId {
+        self.spawn_with_stats(kind, kind.stats(), position, exit)
+    }
+
+    pub fn spawn_with_stats(
+        &mut self,
+        kind: GobletKind,
+        stats: GobletStats,
+        position: GridPosition,
+        exit: Direction,
+    ) -> GobletId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.goblets.push(Goblet {
+            id,
+            kind,
+            stats,
+            hp: stats.max_hp,
+            position,
+            entry: None,
+            spawn_exit: exit,
+            progress: 0,
+            state: GobletState::Moving,
+        });
+        id
+    }
+
+    pub fn goblets(&self) -> &[Goblet] {
+        &self.goblets
+    }
+
+    pub fn goblet(&self, id: GobletId) -> Option<&Goblet> {
+        self.goblets.iter().find(|goblet| goblet.id == id)
+    }
+
+    pub fn goblet_mut(&mut self, id: GobletId) -> Option<&mut Goblet> {
+        self.goblets.iter_mut().find(|goblet| goblet.id == id)
+    }
+
+    pub fn active_count(&self) -> usize {
+        self.goblets.iter().filter(|goblet| goblet.is_active()).count()
+    }
+
+    // sum of payloads of every goblet that made it to a goal
+    pub fn delivered_payload(&self) -> u32 {
+        self.delivered_payload
+    }
+
+    pub fn tick_count(&self) -> u64 {
+        self.tick_count
+    }
+
+    // Sides the goblet can leave its current cell through, where the neighbour will take it
+    fn open_exits(&self, cells: &CellMap, goblet: &Goblet) -> Vec<Direction> {
+        let exits = match goblet.entry {
+            None => vec![goblet.spawn_exit],
+            Some(entry) => match cell_at(cells, goblet.position) {
+                Some(cell) => cell.ports().exits_for(entry),
+                None => Vec::new(),
+            },
+        };
+        exits
+            .into_iter()
+            .filter(|exit| {
+                let target = self.topology.port_neighbour(goblet.position, *exit);
+                if self.goals.contains(&target) {
+                    return true;
+                }
+                match cell_at(cells, target) {
+                    Some(cell) if cell.key != BlockKeys::Void => {
+                        matches!(cell.ports().port(exit.opposite()), Some(flow) if flow.accepts())
+                    }
+                    _ => false,
+                }
+            })
+            .collect()
+    }
+
+    // Advances every active goblet by one tick (in spawn order), returns what happened
+    pub fn tick(&mut self, cells: &CellMap) -> Vec<GobletEvent> {
+        let mut events = Vec::new();
+        self.tick_count += 1;
+
+        for index in 0..self.goblets.len() {
+            let goblet = self.goblets[index];
+            if !goblet.is_active() {
+                continue;
+            }
+            // still on its way through the current cell
+            let progress = goblet.progress + 1;
+            if progress < goblet.stats.ticks_per_cell.max(1) {
+                self.goblets[index].progress = progress;
+                continue;
+            }
+
+            let exits = self.open_exits(cells, &goblet);
+            let goblet = &mut self.goblets[index];
+            if exits.is_empty() {
+                // wait at the end of the cell until there is somewhere to go (or blow up)
+                goblet.progress = progress;
+                if goblet.stats.explode_on_dead_end {
+                    goblet.state = GobletState::Exploded;
+                    events.push(GobletEvent::Exploded {
+                        id: goblet.id,
+                        position: goblet.position,
+                    });
+                } else if goblet.state != GobletState::Stuck {
+                    goblet.state = GobletState::Stuck;
+                    events.push(GobletEvent::Stuck {
+                        id: goblet.id,
+                        position: goblet.position,
+                    });
+                }
+                continue;
+            }
+
+            let exit = exits[goblet.id as usize % exits.len()];
+            let from = goblet.position;
+            let to = self.topology.port_neighbour(from, exit);
+            goblet.position = to;
+            goblet.entry = Some(exit.opposite());
+            goblet.progress = 0;
+            goblet.state = GobletState::Moving;
+            events.push(GobletEvent::Moved {
+                id: goblet.id,
+                from,
+                to,
+            });
+            if self.goals.contains(&to) {
+                goblet.state = GobletState::Arrived;
+                self.delivered_payload += goblet.stats.payload;
+                events.push(GobletEvent::Arrived {
+                    id: goblet.id,
+                    goal: to,
+                    payload: goblet.stats.payload,
+                });
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orientation::Rotation;
+    use crate::test_utils::make_map;
+
+    // S = = G
+    fn straight_road() -> CellMap {
+        make_map(
+            4,
+            1,
+            &[
+                (1, 0, BlockKeys::Router1Straight, Rotation::Deg90),
+                (2, 0, BlockKeys::Router1Straight, Rotation::Deg90),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_speed_and_arrival() {
+        let cells = straight_road();
+        let start = GridPosition::new(0, 0);
+        let goal = GridPosition::new(3, 0);
+        let mut sim = GobletSimulator::new(vec![goal]);
+        let runner = sim.spawn(GobletKind::Runner, start, Direction::East);
+        let hauler = sim.spawn(GobletKind::Hauler, start, Direction::East);
+
+        // runner moves a cell per tick, so it is in after 3 ticks
+        for _ in 0..3 {
+            sim.tick(&cells);
+        }
+        assert_eq!(sim.goblet(runner).unwrap().state, GobletState::Arrived);
+        assert_eq!(sim.goblet(hauler).unwrap().position, GridPosition::new(0, 0));
+        assert_eq!(sim.delivered_payload(), GobletKind::Runner.stats().payload);
+
+        // hauler takes 4 ticks per cell
+        for _ in 0..9 {
+            sim.tick(&cells);
+        }
+        let hauler = sim.goblet(hauler).unwrap();
+        assert_eq!(hauler.state, GobletState::Arrived);
+        assert_eq!(sim.active_count(), 0);
+    }
+
+    #[test]
+    fn test_dead_end() {
+        // S = (void)
+        let cells = make_map(3, 1, &[(1, 0, BlockKeys::Router1Straight, Rotation::Deg90)]);
+        let start = GridPosition::new(0, 0);
+        let mut sim = GobletSimulator::new(vec![]);
+        let grunt = sim.spawn(GobletKind::Grunt, start, Direction::East);
+        let bomber = sim.spawn(GobletKind::Bomber, start, Direction::East);
+        let mut events = Vec::new();
+        for _ in 0..10 {
+            events.extend(sim.tick(&cells));
+        }
+        assert_eq!(sim.goblet(grunt).unwrap().state, GobletState::Stuck);
+        assert_eq!(sim.goblet(bomber).unwrap().state, GobletState::Exploded);
+        // stuck is only reported once
+        let stuck_count = events
+            .iter()
+            .filter(|event| matches!(event, GobletEvent::Stuck { .. }))
+            .count();
+        assert_eq!(stuck_count, 1);
+
+        // player fixes the road, and the stuck one carries on
+        let cells = make_map(
+            3,
+            1,
+            &[
+                (1, 0, BlockKeys::Router1Straight, Rotation::Deg90),
+                (2, 0, BlockKeys::Router1Straight, Rotation::Deg90),
+            ],
+        );
+        sim.tick(&cells);
+        let grunt = sim.goblet(grunt).unwrap();
+        assert_eq!(grunt.position, GridPosition::new(2, 0));
+        assert_eq!(grunt.state, GobletState::Moving);
+    }
+
+    #[test]
+    fn test_router_spreads_by_id() {
+        // flowing north into a tee at (1,1) splits west to goal (0,1) and east to goal (2,1)
+        let cells = make_map(3, 3, &[(1, 1, BlockKeys::Router1Tee, Rotation::Deg0)]);
+        let west = GridPosition::new(0, 1);
+        let east = GridPosition::new(2, 1);
+        let mut sim = GobletSimulator::new(vec![west, east]);
+        let a = sim.spawn(GobletKind::Runner, GridPosition::new(1, 2), Direction::North);
+        let b = sim.spawn(GobletKind::Runner, GridPosition::new(1, 2), Direction::North);
+        sim.tick(&cells);
+        sim.tick(&cells);
+        let goals: Vec<GridPosition> = [a, b]
+            .iter()
+            .map(|id| sim.goblet(*id).unwrap().position)
+            .collect();
+        assert!(goals.contains(&west) && goals.contains(&east));
+    }
+}
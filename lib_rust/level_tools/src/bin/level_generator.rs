@@ -0,0 +1,123 @@
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use internal_primitives::generator::{generate, Difficulty, GeneratorSettings};
+
+// Writes out a procedurally generated level the solver was able to win (see
+// internal_primitives::generator), i.e.:
+//
+//      cargo run -p level_tools --bin level_generator -- --difficulty hard --out daily.txt
+//
+// Without --seed the seed is the number of days since 1970-01-01 (UTC), so everyone running
+// it on the same day gets the same "daily" level.
+// Exit code is 0 on success, 1 if no solvable level was found, and 2 on bad usage.
+
+const USAGE: &str = "usage: level_generator [options]
+options:
+    --width <n>         grid width (default 7)
+    --height <n>        grid height (default 9)
+    --difficulty <d>    easy, normal or hard (default normal)
+    --seed <n>          (default: today's date)
+    --attempts <n>      levels to try before giving up (default 50)
+    --out <file>        where to write the level (default: stdout)";
+
+struct Args {
+    settings: GeneratorSettings,
+    out_path: Option<String>,
+}
+
+fn days_since_epoch() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() / (24 * 60 * 60))
+        .unwrap_or(0)
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        settings: GeneratorSettings {
+            seed: days_since_epoch(),
+            ..GeneratorSettings::default()
+        },
+        out_path: None,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("{} is missing a value", name))
+        };
+        match arg.as_str() {
+            "--width" => {
+                parsed.settings.width = value(&arg)?
+                    .parse()
+                    .map_err(|e| format!("bad --width: {}", e))?
+            }
+            "--height" => {
+                parsed.settings.height = value(&arg)?
+                    .parse()
+                    .map_err(|e| format!("bad --height: {}", e))?
+            }
+            "--difficulty" => {
+                let difficulty = value(&arg)?;
+                parsed.settings.difficulty = difficulty
+                    .parse::<Difficulty>()
+                    .map_err(|_| format!("bad --difficulty '{}'", difficulty))?
+            }
+            "--seed" => {
+                parsed.settings.seed = value(&arg)?
+                    .parse()
+                    .map_err(|e| format!("bad --seed: {}", e))?
+            }
+            "--attempts" => {
+                parsed.settings.max_attempts = value(&arg)?
+                    .parse()
+                    .map_err(|e| format!("bad --attempts: {}", e))?
+            }
+            "--out" => parsed.out_path = Some(value(&arg)?),
+            extra => return Err(format!("unexpected argument '{}'", extra)),
+        }
+    }
+    Ok(parsed)
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let generated = match generate(&args.settings) {
+        Ok(generated) => generated,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(1);
+        }
+    };
+
+    // the level goes to stdout (unless --out), everything else to stderr
+    let level = &generated.level;
+    eprintln!(
+        "level '{}' after {} attempt(s), queue_seed {}",
+        level.name, generated.attempts, level.queue_seed
+    );
+    if let Some(best) = generated.report.best.as_ref() {
+        eprintln!(
+            "min_length {}, solver's best score {} ({} cells)",
+            generated.report.min_length, best.summary.total, best.summary.filled_length
+        );
+    }
+    match args.out_path.as_ref() {
+        Some(path) => {
+            if let Err(e) = level.save(Path::new(path)) {
+                eprintln!("{}: {}", path, e);
+                return ExitCode::from(2);
+            }
+            eprintln!("level written to {}", path);
+        }
+        None => print!("{}", level.to_text()),
+    }
+    ExitCode::SUCCESS
+}
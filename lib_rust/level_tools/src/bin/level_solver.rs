@@ -0,0 +1,173 @@
+use std::path::Path;
+use std::process::ExitCode;
+
+use internal_primitives::level::Level;
+use internal_primitives::session::SessionInput;
+use internal_primitives::solver::{solve, SolverSettings};
+use internal_primitives::tile_queue::TileQueue;
+
+// Tells a level designer whether a level can be won with the pieces its queue_seed hands out
+// (see internal_primitives::solver for how the search works), i.e.:
+//
+//      cargo run -p level_tools --bin level_solver -- ../app_godot/levels/example_01.txt
+//
+// Exit code is 0 if the minimum length can be reached, 1 if not, and 2 on bad usage/level.
+
+const USAGE: &str = "usage: level_solver <level file> [options]
+options:
+    --queue-size <n>    pieces shown ahead in the queue (default 5)
+    --oriented          queue hands out pieces in random orientations
+    --pieces <n>        how far into the queue to look (default 40)
+    --nodes <n>         search budget (default 500000)
+    --replay <file>     write the best placement sequence as a replay file";
+
+struct Args {
+    level_path: String,
+    queue_size: usize,
+    oriented: bool,
+    settings: SolverSettings,
+    replay_path: Option<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        level_path: String::new(),
+        queue_size: 5,
+        oriented: false,
+        settings: SolverSettings::default(),
+        replay_path: None,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("{} is missing a value", name))
+        };
+        match arg.as_str() {
+            "--queue-size" => {
+                parsed.queue_size = value(&arg)?
+                    .parse()
+                    .map_err(|e| format!("bad --queue-size: {}", e))?
+            }
+            "--oriented" => parsed.oriented = true,
+            "--pieces" => {
+                parsed.settings.max_pieces = value(&arg)?
+                    .parse()
+                    .map_err(|e| format!("bad --pieces: {}", e))?
+            }
+            "--nodes" => {
+                parsed.settings.max_nodes = value(&arg)?
+                    .parse()
+                    .map_err(|e| format!("bad --nodes: {}", e))?
+            }
+            "--replay" => parsed.replay_path = Some(value(&arg)?),
+            option if option.starts_with("--") => {
+                return Err(format!("unknown option '{}'", option))
+            }
+            path if parsed.level_path.is_empty() => parsed.level_path = path.to_string(),
+            extra => return Err(format!("unexpected argument '{}'", extra)),
+        }
+    }
+    if parsed.level_path.is_empty() {
+        return Err("missing level file".to_string());
+    }
+    Ok(parsed)
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let level = match Level::load(Path::new(&args.level_path)) {
+        Ok(level) => level,
+        Err(e) => {
+            eprintln!("{}: {}", args.level_path, e);
+            return ExitCode::from(2);
+        }
+    };
+    let weights = level.queue_weights.clone();
+    let queue = match args.oriented {
+        true => TileQueue::new_oriented(args.queue_size, level.queue_seed, weights),
+        false => TileQueue::new(args.queue_size, level.queue_seed, weights),
+    };
+    let report = match solve(&level, &queue, &args.settings) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}: {}", args.level_path, e);
+            return ExitCode::from(2);
+        }
+    };
+
+    println!(
+        "level '{}' ({} x {}), queue_seed {}",
+        level.name, level.width, level.height, level.queue_seed
+    );
+    println!(
+        "searched {} paths ({})",
+        report.nodes,
+        match report.complete {
+            true => "all of them",
+            false => "budget reached, there may be better ones",
+        }
+    );
+    println!("longest path found: {} cells", report.longest_path);
+    let best = match report.best.as_ref() {
+        Some(best) => best,
+        None => {
+            println!("min_length {}: NOT reachable", report.min_length);
+            return ExitCode::from(1);
+        }
+    };
+    let summary = best.summary;
+    println!(
+        "min_length {}: {}",
+        report.min_length,
+        match summary.won {
+            true => "reachable",
+            false => "NOT reachable",
+        }
+    );
+    println!(
+        "best score: {} (filled {}, crossings {}, unused {}, replaced {})",
+        summary.total,
+        summary.filled_length,
+        summary.cross_bonuses,
+        summary.unused_pieces,
+        summary.replaced_pieces
+    );
+    println!("placements (in queue order):");
+    let mut upcoming = queue.clone();
+    for (index, input) in best.inputs.iter().enumerate() {
+        if let SessionInput::Place {
+            position,
+            orientation,
+        } = input
+        {
+            let on_path = best.path.contains(position);
+            println!(
+                "  {:>3}. {:<16} at {},{} rotated {:>3}{}{}",
+                index + 1,
+                upcoming.get_head().key.as_str(),
+                position.x,
+                position.y,
+                orientation.rotation_degrees(),
+                if orientation.flipped { " flipped" } else { "" },
+                if on_path { "" } else { "  (dumped)" }
+            );
+        }
+    }
+    if let Some(path) = args.replay_path.as_ref() {
+        if let Err(e) = best.replay.save(Path::new(path)) {
+            eprintln!("{}: {}", path, e);
+            return ExitCode::from(2);
+        }
+        println!("replay written to {}", path);
+    }
+    match summary.won {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::from(1),
+    }
+}